@@ -1,12 +1,26 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
-use macroquad::prelude::*;
 use roundabout_sim::*;
 use std::env;
 
+const DEADLOCK_TIME: Real = 100.0; // a run stops once no car moved for this long
+
 fn help() {
     println!("     : cargo run -- <path_to_json>");
     println!("usage: cargo run -- gen_circular <n_cars>");
     println!("usage: cargo run -- gen_random <n_cars> <n_inter> <r_lanes[0]> <r_lanes[1]> ...");
+    println!("usage: cargo run -- snapshot <path_to_json> <t> <path_to_snapshot>");
+    println!("usage: cargo run -- validate <path_to_json>");
+    println!("usage: cargo run -- migrate <path_to_json>");
+    println!("usage: cargo run -- convert <path_from> <path_to>");
+    println!("usage: cargo run -- trajectory <path_to_json> <interval> <path_to_output>");
+    println!("usage: cargo run -- network <path_to_json> <max_t>");
+}
+
+/**
+    simulation of the file @path, exits with 1 once its problems are printed
+*/
+fn load(path: &str) -> RoundaboutSim {
+    RoundaboutSim::from_file(path).unwrap_or_else(|| std::process::exit(1))
 }
 
 fn main() {
@@ -21,7 +35,7 @@ fn main() {
         let n_dst = args[3].parse::<usize>().expect("expect usize");
         let mut r_lanes = vec![];
         for arg in args.iter().skip(4) {
-            r_lanes.push(arg.parse::<f32>().expect("expect f32"));
+            r_lanes.push(arg.parse::<Real>().expect("expect a number"));
         }
         let jobj = RoundaboutSimSetting::gen_random(n_cars, n_dst, &r_lanes);
         println!("{}", json::stringify(jobj));
@@ -32,7 +46,94 @@ fn main() {
         let i = args[2].parse().expect("expect usize");
         let jobj = RoundaboutSimSetting::gen_circular(i);
         println!("{}", json::stringify(jobj));
+    } else if args[1] == "snapshot" {
+        if args.len() < 5 {
+            help();
+            return;
+        }
+        let mut sim = load(&args[2]);
+        let t = args[3].parse::<Real>().expect("expect a number");
+        while sim.t < t && !sim.update() {}
+        std::fs::write(&args[4], json::stringify_pretty(sim.snapshot(), 2))
+            .expect("cannot write the snapshot");
+        println!("snapshot at time {} written to {}", sim.t, args[4]);
+    } else if args[1] == "validate" {
+        if args.len() < 3 {
+            help();
+            return;
+        }
+        let jobj = match config::read_json(&args[2]) {
+            Ok(jobj) => jobj,
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        };
+        let problems = validate(&jobj);
+        for problem in &problems {
+            println!("{problem}");
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        println!("{} is valid", args[2]);
+    } else if args[1] == "migrate" {
+        if args.len() < 3 {
+            help();
+            return;
+        }
+        match Config::from_file(&args[2]) {
+            Ok(config) => println!("{}", config.write(Format::of(&args[2]))),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    } else if args[1] == "convert" {
+        if args.len() < 4 {
+            help();
+            return;
+        }
+        let config = Config::from_file(&args[2]).expect("cannot read the configuration");
+        std::fs::write(&args[3], config.write(Format::of(&args[3])))
+            .expect("cannot write the configuration");
+        println!("{} written to {}", args[2], args[3]);
+    } else if args[1] == "trajectory" {
+        if args.len() < 5 {
+            help();
+            return;
+        }
+        let mut sim = load(&args[2]);
+        let interval = args[3].parse::<Real>().expect("expect a number");
+        let mut trajectory = Trajectory::new(interval);
+        trajectory.record(&sim);
+        let reason = sim.run_until(&mut [], |sim| trajectory.record(sim));
+        if reason != StopReason::AllFinished {
+            println!("===== stopped by {reason:?} at time {} =====", sim.t);
+        }
+        let file = std::fs::File::create(&args[4]).expect("cannot create the output");
+        let mut writer = std::io::BufWriter::new(file);
+        if args[4].ends_with(".csv") {
+            trajectory.write_csv(&mut writer)
+        } else {
+            trajectory.write_columnar(&mut writer)
+        }
+        .expect("cannot write the trajectory");
+        println!("{} rows written to {}", trajectory.len(), args[4]);
+    } else if args[1] == "network" {
+        if args.len() < 4 {
+            help();
+            return;
+        }
+        let max_t = args[3].parse::<Real>().expect("expect a number");
+        let network = network_run(&args[2], max_t).expect("format error");
+        print!("{network}");
     } else {
-        sim_run(&args[1], -1.0).unwrap();
+        let mut sim = load(&args[1]);
+        let reason = sim.run_until(&mut [StopCondition::Deadlock(DEADLOCK_TIME)], |_| {});
+        if reason != StopReason::AllFinished {
+            println!("===== stopped by {reason:?} at time {} =====", sim.t);
+        }
+        print!("{}", sim.stats());
     }
 }
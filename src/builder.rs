@@ -0,0 +1,267 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::Real;
+use crate::config::{
+    CONFIG_VERSION, CarConfig, Config, EventConfig, IncidentConfig, ShapeConfig, SolidLineConfig,
+    SpeedLimitConfig,
+};
+use crate::event::{Event, EventAction};
+use crate::geometry::Shape;
+use crate::incident::{Incident, IncidentKind};
+use crate::setting::{Circulation, Engine, SwitchPolicy};
+use crate::validate::Problem;
+use crate::{RoundaboutSim, RoundaboutSimSetting};
+use indexmap::IndexMap;
+
+/**
+    Simulation built from Rust instead of a configuration file, checked like one by
+    validate when built
+*/
+#[derive(Debug, Clone)]
+pub struct RoundaboutSimBuilder {
+    config: Config,
+    problems: Vec<Problem>, // found while adding, like a car id given twice
+}
+
+impl Default for RoundaboutSimBuilder {
+    fn default() -> RoundaboutSimBuilder {
+        let setting = RoundaboutSimSetting::default();
+        RoundaboutSimBuilder {
+            config: Config {
+                version: CONFIG_VERSION,
+                comment: None,
+                n_inter: setting.n_inter,
+                r_lanes: setting.r_lanes,
+                shapes: vec![],
+                tick: setting.tick,
+                switch_policy: None,
+                circulation: None,
+                engine: None,
+                check_invariants: None,
+                driver: None,
+                exit_lanes: vec![],
+                solid_lines: vec![],
+                speed_limits: vec![],
+                lateral_accel: None,
+                demand: None,
+                stats_bin: None,
+                classes: IndexMap::new(),
+                crossings: vec![],
+                signals: vec![],
+                events: vec![],
+                incidents: vec![],
+                init: IndexMap::new(),
+            },
+            problems: vec![],
+        }
+    }
+}
+
+impl RoundaboutSimBuilder {
+    /**
+        the defaults of RoundaboutSimSetting and no cars
+    */
+    pub fn new() -> RoundaboutSimBuilder {
+        RoundaboutSimBuilder::default()
+    }
+    /**
+        @n_inter intersections evenly spaced on lanes of radius @r_lanes, outermost first
+    */
+    pub fn geometry(mut self, n_inter: usize, r_lanes: &[Real]) -> RoundaboutSimBuilder {
+        self.config.n_inter = n_inter;
+        self.config.r_lanes = r_lanes.to_vec();
+        self.config.shapes.clear();
+        self
+    }
+    /**
+        @n_inter intersections evenly spaced on lanes of @shapes, outermost first
+    */
+    pub fn shapes(mut self, n_inter: usize, shapes: &[Shape]) -> RoundaboutSimBuilder {
+        self.config.n_inter = n_inter;
+        self.config.r_lanes.clear();
+        self.config.shapes = shapes
+            .iter()
+            .map(|shape| match shape {
+                Shape::Circle { r } => ShapeConfig::Circle { r: *r },
+                Shape::Ellipse { a, b } => ShapeConfig::Ellipse { a: *a, b: *b },
+                Shape::Polyline { points, smooth } => ShapeConfig::Polyline {
+                    points: points.clone(),
+                    smooth: smooth.then_some(true),
+                },
+            })
+            .collect();
+        self
+    }
+    /**
+        cars may leave at intersection @inter from @lanes only, lane 0 if not given
+    */
+    pub fn exit_lanes(mut self, inter: usize, lanes: &[usize]) -> RoundaboutSimBuilder {
+        let exit_lanes = &mut self.config.exit_lanes;
+        if exit_lanes.len() <= inter {
+            exit_lanes.resize(inter + 1, vec![0]);
+        }
+        exit_lanes[inter] = lanes.to_vec();
+        self
+    }
+    /**
+        no switching between @lane and @lane + 1 over the arc from @from to @to
+    */
+    pub fn solid_line(mut self, lane: usize, from: Real, to: Real) -> RoundaboutSimBuilder {
+        self.config
+            .solid_lines
+            .push(SolidLineConfig { lane, from, to });
+        self
+    }
+    /**
+        cars on @lane drive at most at @vel, over the arc from @arc.0 to @arc.1 or the
+        whole lane if @arc is None
+    */
+    pub fn speed_limit(
+        mut self,
+        lane: usize,
+        vel: Real,
+        arc: Option<(Real, Real)>,
+    ) -> RoundaboutSimBuilder {
+        self.config.speed_limits.push(SpeedLimitConfig {
+            lane,
+            vel,
+            from: arc.map(|(from, _)| from),
+            to: arc.map(|(_, to)| to),
+        });
+        self
+    }
+    /**
+        cars on a lane of radius r drive at most at sqrt(@lateral_accel * r), r being the
+        radius of curvature where they are on a shaped lane
+    */
+    pub fn lateral_accel(mut self, lateral_accel: Real) -> RoundaboutSimBuilder {
+        self.config.lateral_accel = Some(lateral_accel);
+        self
+    }
+    pub fn tick(mut self, tick: Real) -> RoundaboutSimBuilder {
+        self.config.tick = tick;
+        self
+    }
+    pub fn switch_policy(mut self, switch_policy: SwitchPolicy) -> RoundaboutSimBuilder {
+        self.config.switch_policy = Some(switch_policy);
+        self
+    }
+    pub fn circulation(mut self, circulation: Circulation) -> RoundaboutSimBuilder {
+        self.config.circulation = Some(circulation);
+        self
+    }
+    pub fn engine(mut self, engine: Engine) -> RoundaboutSimBuilder {
+        self.config.engine = Some(engine);
+        self
+    }
+    pub fn check_invariants(mut self, check_invariants: bool) -> RoundaboutSimBuilder {
+        self.config.check_invariants = Some(check_invariants);
+        self
+    }
+    /**
+        statistics also in time bins of @width
+    */
+    pub fn stats_bin(mut self, width: Real) -> RoundaboutSimBuilder {
+        self.config.stats_bin = Some(width);
+        self
+    }
+    /**
+        driver of the cars not given one, one of DRIVER_NAMES
+    */
+    pub fn driver(mut self, driver: &str) -> RoundaboutSimBuilder {
+        self.config.driver = Some(driver.to_string());
+        self
+    }
+    /**
+        car @id on @lane at angle @theta going at @vel to intersection @dst, with the
+        default driver if @driver is None
+    */
+    pub fn car(
+        mut self,
+        id: usize,
+        lane: usize,
+        theta: Real,
+        vel: Real,
+        dst: usize,
+        driver: Option<&str>,
+    ) -> RoundaboutSimBuilder {
+        let car = CarConfig {
+            comment: None,
+            class: None,
+            dst,
+            vel: Some(vel),
+            lane,
+            theta,
+            driver: driver.map(str::to_string),
+        };
+        if self.config.init.insert(id, car).is_some() {
+            self.problems.push(Problem {
+                path: format!("$.init.{id}"),
+                message: "car added twice".to_string(),
+            });
+        }
+        self
+    }
+    /**
+        scripted change to a car during the simulation
+    */
+    pub fn event(mut self, event: Event) -> RoundaboutSimBuilder {
+        let Event { t, car, action } = event;
+        self.config.events.push(match action {
+            EventAction::Remove => EventConfig::Remove { t, car },
+            EventAction::Reroute(dst) => EventConfig::Reroute { t, car, dst },
+            EventAction::SetVel(vel) => EventConfig::SetVel { t, car, vel },
+        });
+        self
+    }
+    /**
+        incident from @incident.start until @incident.end, its active flag is ignored
+    */
+    pub fn incident(mut self, incident: Incident) -> RoundaboutSimBuilder {
+        let start = Some(incident.start);
+        let end = incident.end.is_finite().then_some(incident.end);
+        self.config.incidents.push(match incident.kind {
+            IncidentKind::LaneBlocked { lane, from, to } => IncidentConfig::LaneBlocked {
+                lane,
+                from,
+                to,
+                start,
+                end,
+            },
+            IncidentKind::ExitClosed { inter } => IncidentConfig::ExitClosed { inter, start, end },
+            IncidentKind::SlowZone {
+                lane,
+                from,
+                to,
+                vel,
+            } => IncidentConfig::SlowZone {
+                lane,
+                from,
+                to,
+                vel,
+                start,
+                end,
+            },
+        });
+        self
+    }
+    /**
+        the configuration built so far, to be written to a file for example
+    */
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    /**
+        the simulation at time 0, Err with every problem validate finds otherwise
+    */
+    pub fn build(self) -> Result<RoundaboutSim, Vec<Problem>> {
+        let mut problems = self.problems;
+        match self.config.try_build() {
+            Ok(sim) if problems.is_empty() => Ok(sim),
+            Ok(_) => Err(problems),
+            Err(found) => {
+                problems.extend(found);
+                Err(problems)
+            }
+        }
+    }
+}
@@ -1,13 +1,23 @@
-use num_complex::Complex;
+use json::JsonValue;
 use std::cell::RefCell;
-use std::f32::consts::PI;
 use std::rc::Rc;
 
+// floating point type of the simulation, f64 with the "f64" feature
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+use consts::PI;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
 pub type Shared<T> = Rc<RefCell<T>>;
-pub const THETA_ALLOW: f32 = 1e-2 * PI;
-const DRIFT_ALLOW: f32 = 1e-2;
+pub const THETA_ALLOW: Real = 1e-2 * PI;
+const DRIFT_ALLOW: Real = 1e-2;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Action {
     Switch(i32), // switch in/out, > 0 means to inner, == 0 means stop
     Straight,
@@ -17,11 +27,84 @@ pub enum Action {
 pub const SWITCH_OUT: Action = Action::Switch(-1);
 pub const SWITCH_IN: Action = Action::Switch(1);
 
-pub fn unwrap_theta(theta: f32) -> f32 {
-    if theta < 0.0 { theta + 2.0 * PI } else { theta }
+/**
+    json numbers as Real
+*/
+pub trait AsReal {
+    fn as_real(&self) -> Option<Real>;
+}
+
+impl AsReal for JsonValue {
+    fn as_real(&self) -> Option<Real> {
+        // parse the printed digits, as_f64 may be an ulp off the nearest value
+        self.as_number()?.to_string().parse().ok()
+    }
+}
+
+/**
+    @x as json, which has no numbers for infinities and NaN so they become strings
+*/
+pub fn real_to_json(x: Real) -> JsonValue {
+    if x.is_finite() {
+        x.into()
+    } else {
+        x.to_string().into()
+    }
+}
+
+/**
+    the Real written by real_to_json, bit-for-bit
+*/
+pub fn real_from_json(jobj: &JsonValue) -> Option<Real> {
+    match jobj.as_str() {
+        Some(s) => s.parse().ok(),
+        None => jobj.as_real(),
+    }
+}
+
+/**
+    @xs as a json array of real_to_json
+*/
+pub fn reals_to_json(xs: &[Real]) -> JsonValue {
+    xs.iter()
+        .copied()
+        .map(real_to_json)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/**
+    the Reals written by reals_to_json
+*/
+pub fn reals_from_json(jobj: &JsonValue) -> Option<Vec<Real>> {
+    jobj.members().map(real_from_json).collect()
+}
+
+/**
+    @theta in [0, 2 PI)
+*/
+pub fn unwrap_theta(theta: Real) -> Real {
+    theta.rem_euclid(2.0 * PI)
+}
+
+/**
+    @theta in (-PI, PI], the range of Complex::arg
+*/
+pub fn wrap_theta(theta: Real) -> Real {
+    let theta = unwrap_theta(theta);
+    if theta > PI { theta - 2.0 * PI } else { theta }
+}
+
+/**
+    whether a car @offset away from its lane radially still counts as on the lane
+*/
+pub fn is_on_lane(offset: Real) -> bool {
+    offset.abs() <= DRIFT_ALLOW
 }
 
-pub fn is_on_lane(pos: &Complex<f32>, r_lane: f32) -> bool {
-    let ideal_pos = Complex::from_polar(r_lane, pos.arg());
-    (pos - ideal_pos).norm() <= DRIFT_ALLOW
+/**
+    whether @theta lies on the arc from @from to @to (counter-clockwise)
+*/
+pub fn in_arc(theta: Real, from: Real, to: Real) -> bool {
+    (theta - from).rem_euclid(2.0 * PI) <= (to - from).rem_euclid(2.0 * PI)
 }
@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::Real;
+use crate::demand::Interpolation;
+use crate::setting::{Circulation, Engine, SwitchPolicy};
+use crate::validate::{Problem, validate};
+use crate::{RoundaboutSim, RoundaboutSimSetting};
+use indexmap::IndexMap;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_VERSION: u32 = 2; // files without "version" are version 1
+
+/**
+    File formats of a configuration, all with the structure of the json one
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /**
+        format given by the extension of @path, json unless it is .toml, .yaml or .yml
+    */
+    pub fn of(path: &str) -> Format {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+}
+
+/**
+    Typed configuration file, every key of the README schema and nothing else
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub n_inter: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub r_lanes: Vec<Real>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shapes: Vec<ShapeConfig>, // instead of r_lanes
+    pub tick: Real,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub switch_policy: Option<SwitchPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circulation: Option<Circulation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<Engine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_invariants: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>, // default driver of every car
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exit_lanes: Vec<Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub solid_lines: Vec<SolidLineConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub speed_limits: Vec<SpeedLimitConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lateral_accel: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demand: Option<DemandConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_bin: Option<Real>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub classes: IndexMap<String, ClassConfig>, // in the order of the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub crossings: Vec<CrossingConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signals: Vec<SignalConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub incidents: Vec<IncidentConfig>,
+    pub init: IndexMap<usize, CarConfig>, // by car id
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum ShapeConfig {
+    Circle {
+        r: Real,
+    },
+    Ellipse {
+        a: Real,
+        b: Real,
+    },
+    Polyline {
+        points: Vec<[Real; 2]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smooth: Option<bool>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolidLineConfig {
+    pub lane: usize,
+    pub from: Real,
+    pub to: Real,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeedLimitConfig {
+    pub lane: usize,
+    pub vel: Real,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<Real>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DemandConfig {
+    pub od: Vec<Vec<Real>>,
+    pub duration: Real,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vel: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classes: Option<IndexMap<String, Real>>, // class mix by weight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ProfileConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
+    pub points: Vec<ProfilePointConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilePointConfig {
+    pub t: Real,
+    pub factors: Vec<Real>, // for each intersection
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vel: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accel: Option<Real>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lanes: Option<Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CrossingConfig {
+    pub inter: usize,
+    pub rate: Real,
+    pub cross_time: Real,
+    pub until: Real,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spillback: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum SignalConfig {
+    FixedTime {
+        inter: usize,
+        green: Real,
+        red: Real,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<Real>,
+    },
+    QueueActuated {
+        inter: usize,
+        min_green: Real,
+        max_green: Real,
+        min_red: Real,
+        max_red: Real,
+        queue: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum EventConfig {
+    Remove { t: Real, car: usize },
+    Reroute { t: Real, car: usize, dst: usize },
+    SetVel { t: Real, car: usize, vel: Real },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum IncidentConfig {
+    LaneBlocked {
+        lane: usize,
+        from: Real,
+        to: Real,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<Real>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<Real>,
+    },
+    ExitClosed {
+        inter: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<Real>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<Real>,
+    },
+    SlowZone {
+        lane: usize,
+        from: Real,
+        to: Real,
+        vel: Real,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<Real>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<Real>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CarConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    pub dst: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vel: Option<Real>,
+    pub lane: usize,
+    pub theta: Real,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+}
+
+/**
+    @content written in @format as json, not migrated
+*/
+pub fn parse_json(content: &str, format: Format) -> Result<JsonValue, String> {
+    let dumped = match format {
+        Format::Json => return json::parse(content).map_err(|err| err.to_string()),
+        Format::Toml => {
+            let table: toml::Table = toml::from_str(content).map_err(|err| err.to_string())?;
+            serde_json::to_string(&table)
+        }
+        Format::Yaml => {
+            let value: serde_yaml::Value =
+                serde_yaml::from_str(content).map_err(|err| err.to_string())?;
+            serde_json::to_string(&value)
+        }
+    };
+    json::parse(&dumped.map_err(|err| err.to_string())?).map_err(|err| err.to_string())
+}
+
+/**
+    content of the file @path as json, in the format of its extension
+*/
+pub fn read_json(path: &str) -> Result<JsonValue, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    parse_json(&content, Format::of(path)).map_err(|err| format!("{path}: {err}"))
+}
+
+/**
+    @jobj upgraded to CONFIG_VERSION, Err if it is from a newer version
+*/
+pub fn migrate(jobj: &JsonValue) -> Result<JsonValue, String> {
+    let mut jobj = jobj.clone();
+    let mut version = if jobj.has_key("version") {
+        jobj["version"]
+            .as_u32()
+            .ok_or("version must be a non-negative integer")?
+    } else {
+        1
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "version {version} is newer than the supported {CONFIG_VERSION}"
+        ));
+    }
+    if version == 1 {
+        // cars used to carry their simulation state as well, it was never read
+        for (_, car) in jobj["init"].entries_mut() {
+            for key in ["id", "pos", "action", "r"] {
+                car.remove(key);
+            }
+        }
+        version = 2;
+    }
+    jobj["version"] = version.into();
+    Ok(jobj)
+}
+
+impl Config {
+    /**
+        configuration from @jobj of any version, Err describes the first unknown key
+        or value of the wrong type
+    */
+    pub fn from_json(jobj: &JsonValue) -> Result<Config, String> {
+        let jobj = migrate(jobj)?;
+        let value: serde_json::Value =
+            serde_json::from_str(&jobj.dump()).map_err(|err| err.to_string())?;
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+    /**
+        configuration in the file @path, in the format of its extension
+    */
+    pub fn from_file(path: &str) -> Result<Config, String> {
+        Config::from_json(&read_json(path)?).map_err(|err| format!("{path}: {err}"))
+    }
+    /**
+        the configuration written in @format, comments are not kept
+    */
+    pub fn write(&self, format: Format) -> String {
+        match format {
+            Format::Json => json::stringify_pretty(self.to_json(), 4),
+            Format::Toml => toml::to_string(self).expect("configs are always serializable"),
+            Format::Yaml => serde_yaml::to_string(self).expect("configs are always serializable"),
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let dumped = serde_json::to_string(self).expect("configs are always serializable");
+        json::parse(&dumped).expect("serde_json writes valid json")
+    }
+    /**
+        simulation at time 0, None if validate finds any problem
+    */
+    pub fn build(&self) -> Option<RoundaboutSim> {
+        self.try_build().ok()
+    }
+    /**
+        simulation at time 0, Err with every problem validate finds otherwise
+    */
+    pub fn try_build(&self) -> Result<RoundaboutSim, Vec<Problem>> {
+        let problems = validate(&self.to_json());
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        self.build_unchecked().ok_or_else(|| {
+            vec![Problem {
+                path: "$".to_string(),
+                message: "rejected by the simulator".to_string(),
+            }]
+        })
+    }
+    /**
+        simulation at time 0 without validating, None if the values cannot be used
+    */
+    pub(crate) fn build_unchecked(&self) -> Option<RoundaboutSim> {
+        RoundaboutSim::from_config(RoundaboutSimSetting::from_config(self)?, self)
+    }
+}
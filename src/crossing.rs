@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::Real;
+use crate::config::CrossingConfig;
+use json::{JsonValue, object};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/**
+    Zebra crossing on the arm of an intersection, every pedestrian blocks cars
+    entering and leaving at that intersection for @cross_time
+*/
+#[derive(Debug, Clone)]
+pub struct Crossing {
+    pub inter: usize,
+    pub rate: Real,       // pedestrians per unit time
+    pub cross_time: Real, // time for a pedestrian to cross
+    pub until: Real,      // pedestrians arrive in [0, until)
+    pub spillback: bool,  // blocked cars wait on the roundabout instead of on the arm
+    pub seed: u64,
+}
+
+impl Crossing {
+    pub fn from_config(config: &CrossingConfig) -> Crossing {
+        Crossing {
+            inter: config.inter,
+            rate: config.rate,
+            cross_time: config.cross_time,
+            until: config.until,
+            spillback: config.spillback.unwrap_or(false),
+            seed: config.seed.unwrap_or(0),
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            inter: self.inter,
+            rate: self.rate,
+            cross_time: self.cross_time,
+            until: self.until,
+            spillback: self.spillback,
+            seed: self.seed,
+        }
+    }
+    /**
+        pedestrian arrival times, the same seed always gives the same arrivals
+    */
+    pub fn gen_pedestrians(&self) -> Vec<Real> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut pedestrians = vec![];
+        if self.rate <= 0.0 {
+            return pedestrians;
+        }
+        let mut t = 0.0;
+        loop {
+            // exponential inter-arrival time, drawn in f32 so the f64 feature keeps the arrivals
+            t += -(1.0 - Real::from(rng.random::<f32>())).ln() / self.rate;
+            if t >= self.until {
+                break;
+            }
+            pedestrians.push(t);
+        }
+        pedestrians
+    }
+    /**
+        disjoint intervals [start, end) during which the crossing is occupied
+    */
+    pub fn gen_blocked(&self) -> Vec<(Real, Real)> {
+        let mut blocked: Vec<(Real, Real)> = vec![];
+        for t in self.gen_pedestrians() {
+            let end = t + self.cross_time;
+            match blocked.last_mut() {
+                Some(last) if t <= last.1 => last.1 = last.1.max(end),
+                _ => blocked.push((t, end)),
+            }
+        }
+        blocked
+    }
+}
@@ -0,0 +1,270 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{AsReal, Real};
+use crate::config::{DemandConfig, ProfileConfig};
+use json::{JsonValue, object};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Step,   // the multipliers of the last point until the next one
+    Linear, // linear between points
+}
+
+/**
+    Multiplier of the flows entering at each intersection over the day, like a
+    morning and an evening peak. Constant before the first point and after the last
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub interpolation: Interpolation,
+    pub points: Vec<(Real, Vec<Real>)>, // time and multiplier of each entrance, by time
+}
+
+impl Profile {
+    /**
+        profile of a configuration, None if it has no point or a point does not have
+        a factor for each of the @n_inter intersections
+    */
+    pub fn from_config(config: &ProfileConfig, n_inter: usize) -> Option<Profile> {
+        if config
+            .points
+            .iter()
+            .any(|point| point.factors.len() != n_inter)
+        {
+            return None;
+        }
+        let mut points: Vec<(Real, Vec<Real>)> = config
+            .points
+            .iter()
+            .map(|point| (point.t, point.factors.clone()))
+            .collect();
+        (!points.is_empty()).then_some(())?;
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(Profile {
+            interpolation: config.interpolation.unwrap_or(Interpolation::Step),
+            points,
+        })
+    }
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            interpolation: format!("{:?}", self.interpolation),
+            points: self
+                .points
+                .iter()
+                .map(|(t, factors)| object! { t: *t, factors: factors.clone() })
+                .collect::<Vec<_>>(),
+        }
+    }
+    /**
+        multiplier of the flows entering at @from at time @t, 1 for entrances not
+        listed
+    */
+    pub fn factor(&self, from: usize, t: Real) -> Real {
+        let factor = |k: usize| self.points[k].1.get(from).copied().unwrap_or(1.0);
+        let next = self.points.partition_point(|(time, _)| *time <= t);
+        if next == 0 {
+            return factor(0);
+        }
+        if next == self.points.len() || self.interpolation == Interpolation::Step {
+            return factor(next - 1);
+        }
+        let (t0, t1) = (self.points[next - 1].0, self.points[next].0);
+        factor(next - 1) + (factor(next) - factor(next - 1)) * (t - t0) / (t1 - t0)
+    }
+    /**
+        highest multiplier of the flows entering at @from
+    */
+    fn max_factor(&self, from: usize) -> Real {
+        (0..self.points.len())
+            .map(|k| self.points[k].1.get(from).copied().unwrap_or(1.0))
+            .fold(0.0, Real::max)
+    }
+}
+
+/**
+    Origin-destination demand, cars arrive at each intersection as Poisson processes
+*/
+#[derive(Debug, Clone)]
+pub struct Demand {
+    pub od: Vec<Vec<Real>>, // od[i][j]: cars per unit time entering at i and leaving at j
+    pub duration: Real,     // arrivals are generated in [0, duration)
+    pub scale: Real,        // multiplier applied to every flow
+    pub vel: Option<Real>,  // velocity of generated cars, the class velocity if None
+    pub classes: Vec<(String, Real)>, // vehicle class mix, weights need not sum to 1
+    pub seed: u64,
+    pub profile: Option<Profile>, // flows vary over time if given
+}
+
+#[derive(Debug, Clone)]
+pub struct Arrival {
+    pub t: Real,     // time of arrival at the entrance
+    pub from: usize, // entrance
+    pub to: usize,   // destination
+    pub vel: Option<Real>,
+    pub class: String,
+    pub id: Option<usize>, // id of the car, the next free one if None
+}
+
+impl Arrival {
+    pub fn new(jobj: &JsonValue) -> Option<Arrival> {
+        Some(Arrival {
+            t: jobj["t"].as_real()?,
+            from: jobj["from"].as_usize()?,
+            to: jobj["to"].as_usize()?,
+            vel: jobj["vel"].as_real(),
+            class: jobj["class"].as_str()?.to_string(),
+            id: jobj["id"].as_usize(),
+        })
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = object! {
+            t: self.t,
+            from: self.from,
+            to: self.to,
+            class: self.class.as_str(),
+        };
+        if let Some(vel) = self.vel {
+            jobj["vel"] = vel.into();
+        }
+        if let Some(id) = self.id {
+            jobj["id"] = id.into();
+        }
+        jobj
+    }
+}
+
+impl Default for Demand {
+    fn default() -> Demand {
+        Demand {
+            od: vec![],
+            duration: 0.0,
+            scale: 1.0,
+            vel: None,
+            classes: vec![("car".to_string(), 1.0)],
+            seed: 0,
+            profile: None,
+        }
+    }
+}
+
+impl Demand {
+    /**
+        demand of a configuration, None unless the od matrix is @n_inter by @n_inter
+    */
+    pub fn from_config(config: &DemandConfig, n_inter: usize) -> Option<Demand> {
+        if config.od.len() != n_inter || config.od.iter().any(|row| row.len() != n_inter) {
+            return None;
+        }
+        let default = Demand::default();
+        Some(Demand {
+            od: config.od.clone(),
+            duration: config.duration,
+            scale: config.scale.unwrap_or(default.scale),
+            vel: config.vel,
+            classes: match &config.classes {
+                Some(classes) => classes
+                    .iter()
+                    .map(|(name, weight)| (name.clone(), *weight))
+                    .collect(),
+                None => default.classes,
+            },
+            seed: config.seed.unwrap_or(default.seed),
+            profile: match &config.profile {
+                Some(profile) => Some(Profile::from_config(profile, n_inter)?),
+                None => None,
+            },
+        })
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut classes = JsonValue::new_object();
+        for (name, weight) in &self.classes {
+            classes[name.as_str()] = (*weight).into();
+        }
+        let mut jobj = object! {
+            od: self.od.clone(),
+            duration: self.duration,
+            scale: self.scale,
+            classes: classes,
+            seed: self.seed,
+        };
+        if let Some(vel) = self.vel {
+            jobj["vel"] = vel.into();
+        }
+        if let Some(profile) = &self.profile {
+            jobj["profile"] = profile.to_json();
+        }
+        jobj
+    }
+    /**
+        flow from @from to @to at time @t, cars per unit time
+    */
+    pub fn flow(&self, from: usize, to: usize, t: Real) -> Real {
+        let factor = self
+            .profile
+            .as_ref()
+            .map_or(1.0, |profile| profile.factor(from, t));
+        self.od[from][to] * self.scale * factor
+    }
+    /**
+        vehicle class drawn according to the class mix
+    */
+    fn pick_class(&self, rng: &mut StdRng) -> String {
+        let total: Real = self.classes.iter().map(|(_, weight)| weight).sum();
+        let mut x = Real::from(rng.random::<f32>()) * total;
+        for (name, weight) in &self.classes {
+            if x < *weight {
+                return name.clone();
+            }
+            x -= weight;
+        }
+        self.classes
+            .last()
+            .map_or("car".to_string(), |(name, _)| name.clone())
+    }
+    /**
+        all arrivals sorted by time, the same seed always gives the same arrivals.
+        U-turns (od[i][i]) are ignored. With a profile, candidates at the highest
+        flow are kept with the ratio of the flow at their time to it
+    */
+    pub fn gen_arrivals(&self) -> Vec<Arrival> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut arrivals = vec![];
+        for (from, row) in self.od.iter().enumerate() {
+            for (to, flow) in row.iter().enumerate() {
+                let max_factor = self
+                    .profile
+                    .as_ref()
+                    .map_or(1.0, |profile| profile.max_factor(from));
+                let rate = flow * self.scale * max_factor;
+                if from == to || rate <= 0.0 {
+                    continue;
+                }
+                let mut t = 0.0;
+                loop {
+                    // exponential inter-arrival time
+                    t += -(1.0 - Real::from(rng.random::<f32>())).ln() / rate;
+                    if t >= self.duration {
+                        break;
+                    }
+                    if self.profile.is_some()
+                        && Real::from(rng.random::<f32>()) * rate >= self.flow(from, to, t)
+                    {
+                        continue;
+                    }
+                    arrivals.push(Arrival {
+                        t,
+                        from,
+                        to,
+                        vel: self.vel,
+                        class: self.pick_class(&mut rng),
+                        id: None,
+                    });
+                }
+            }
+        }
+        arrivals.sort_by(|a, b| a.t.total_cmp(&b.t));
+        arrivals
+    }
+}
@@ -1,29 +1,55 @@
 use crate::Car;
+use crate::EVENT_EPS;
 use crate::RoundaboutSimSetting;
-use crate::common::is_on_lane;
-use crate::common::{Action, THETA_ALLOW, unwrap_theta};
+use crate::common::{Action, Real, THETA_ALLOW, consts, unwrap_theta};
 use crate::common::{SWITCH_IN, SWITCH_OUT};
-use core::f32;
-use json::JsonValue;
-use num_complex::Complex;
+use crate::common::{in_arc, reals_from_json, reals_to_json, wrap_theta};
+use json::{JsonValue, object};
+
+pub const DRIVER_NAMES: &[&str] = &["ShortestDist", "ShortestTime"]; // for make_boxed
 
 pub trait Driver {
+    /**
+        name understood by DriverFactory::make_boxed
+    */
+    fn name(&self) -> &'static str;
+    /**
+        internal state for snapshots, restored by load_state
+    */
+    fn save_state(&self) -> JsonValue {
+        JsonValue::Null
+    }
+    /**
+        restore the state written by save_state, None if @jobj is malformed
+    */
+    fn load_state(&mut self, _jobj: &JsonValue) -> Option<()> {
+        Some(())
+    }
     fn init(&mut self, _car: &Car, _setting: &RoundaboutSimSetting) {}
-    fn drive(&self, car: &Car, _ts: f32, setting: &RoundaboutSimSetting) -> Action;
-    fn update(&mut self, _car: &Car, _ts: f32, _setting: &RoundaboutSimSetting) {}
+    fn drive(&self, car: &Car, _ts: Real, setting: &RoundaboutSimSetting) -> Action;
+    fn update(&mut self, _car: &Car, _ts: Real, _setting: &RoundaboutSimSetting) {}
+    /**
+        time from @ts until the driver may choose another action if @car keeps its
+        current one, used by the event engine. Sampled every tick unless overridden
+    */
+    fn next_decision(&self, _car: &Car, _ts: Real, setting: &RoundaboutSimSetting) -> Real {
+        setting.tick
+    }
 }
 
 pub struct DriverFactory {}
 
 struct ShortestDistDriver;
 
-const SHORTEST_TIME_DRIVER_MIN_STAY: f32 = 5.0;
-const SHORTEST_TIME_DRIVER_REFRESH: f32 = 20.0;
+const AVOID_TIME: Real = 2.0; // blocked arcs reached within this are avoided
+const MIN_ARC_STEP: Real = 1e-4; // limits changing closer than this are passed over
+const SHORTEST_TIME_DRIVER_MIN_STAY: Real = 5.0;
+const SHORTEST_TIME_DRIVER_REFRESH: Real = 20.0;
 
 struct ShortestTimeDriver {
-    lane_last_ts: Vec<f32>,
-    lane_last_pos: Vec<Complex<f32>>,
-    lane_vel: Vec<f32>,
+    lane_last_ts: Vec<Real>,
+    lane_last_dist: Vec<Real>,
+    lane_vel: Vec<Real>,
     prev_lane: usize,
 }
 
@@ -34,7 +60,7 @@ impl DriverFactory {
     fn make_shortest_time_driver_boxed() -> Box<dyn Driver> {
         Box::new(ShortestTimeDriver {
             lane_last_ts: Vec::new(),
-            lane_last_pos: Vec::new(),
+            lane_last_dist: Vec::new(),
             lane_vel: Vec::new(),
             prev_lane: 0,
         })
@@ -42,46 +68,136 @@ impl DriverFactory {
     fn make_default_driver_boxed() -> Box<dyn Driver> {
         DriverFactory::make_shortest_dist_driver_boxed()
     }
-    pub fn make_boxed_from_json(jobj: &JsonValue) -> Box<dyn Driver> {
-        if let JsonValue::Short(short) = jobj {
-            let name = short.as_str();
-            if name == "ShortestDist" {
-                DriverFactory::make_shortest_dist_driver_boxed()
-            } else if name == "ShortestTime" {
-                DriverFactory::make_shortest_time_driver_boxed()
-            } else {
-                DriverFactory::make_default_driver_boxed()
-            }
+    pub fn make_boxed(name: &str) -> Box<dyn Driver> {
+        if name == "ShortestDist" {
+            DriverFactory::make_shortest_dist_driver_boxed()
+        } else if name == "ShortestTime" {
+            DriverFactory::make_shortest_time_driver_boxed()
         } else {
             DriverFactory::make_default_driver_boxed()
         }
     }
+    pub fn make_boxed_from_json(jobj: &JsonValue) -> Box<dyn Driver> {
+        match jobj.as_str() {
+            Some(name) => DriverFactory::make_boxed(name),
+            None => DriverFactory::make_default_driver_boxed(),
+        }
+    }
+}
+
+/**
+    Switch towards the nearest exit lane when @car is about to pass the last point
+    it can switch before its destination, None if nothing needs to be done
+*/
+fn exit_action(car: &Car, setting: &RoundaboutSimSetting) -> Option<Action> {
+    if setting.can_exit(car.arm, car.lane) || setting.exit_closed(car.arm) {
+        // a closed exit is missed whatever the lane
+        return None;
+    }
+    let exit_lane = setting.nearest_exit_lane(car.arm, car.lane);
+    let diff_lane = if exit_lane < car.lane { -1 } else { 1 };
+    let last_theta = setting.last_switch_theta(car.lane, diff_lane, car.dst_theta());
+    let rem_theta = wrap_theta(last_theta - car.theta());
+    // unlike the destination, the car is not stopped at the start of a solid line
+    let step_theta = if last_theta == car.dst_theta() {
+        0.0
+    } else {
+        car.vel * setting.tick / setting.r_lanes[car.lane]
+    };
+    if rem_theta.abs() <= THETA_ALLOW || (0.0..=step_theta).contains(&rem_theta) {
+        Some(Action::Switch(diff_lane))
+    } else {
+        None
+    }
+}
+
+/**
+    whether @car would find no blocked arc on @lane before its destination within
+    AVOID_TIME, if it were there
+*/
+fn lane_clear(car: &Car, lane: usize, setting: &RoundaboutSimSetting) -> bool {
+    let ahead = setting.obstacle_theta(lane, car.theta());
+    !setting.lane_blocked(lane, car.theta())
+        && (ahead > car.rem_theta() || ahead * setting.r_lanes[lane] > car.vel * AVOID_TIME)
+}
+
+/**
+    time for @car to drive @theta ahead on @lane at its velocity, slowed down to the
+    speed limits on the way
+*/
+fn arc_time(car: &Car, lane: usize, theta: Real, setting: &RoundaboutSimSetting) -> Real {
+    let mut from = car.theta();
+    let mut rem = theta;
+    let mut time = 0.0;
+    while rem > 0.0 {
+        let step = setting
+            .limit_boundary_theta(lane, from)
+            .max(MIN_ARC_STEP)
+            .min(rem);
+        let speed = car.vel.min(setting.speed_limit(lane, from + 0.5 * step));
+        time += step * setting.r_lanes[lane] / speed;
+        from += step;
+        rem -= step;
+    }
+    time
+}
+
+/**
+    Switch to an adjacent clear lane, inwards first, when a blocked arc is ahead of
+    @car on its lane, None if nothing needs to be done
+*/
+fn avoid_action(car: &Car, setting: &RoundaboutSimSetting) -> Option<Action> {
+    if !car.is_on_lane() || lane_clear(car, car.lane, setting) {
+        return None;
+    }
+    [SWITCH_IN, SWITCH_OUT].into_iter().find(|action| {
+        let Action::Switch(diff_lane) = *action else {
+            return false;
+        };
+        car.can_switch(diff_lane, setting)
+            && lane_clear(car, (car.lane as i32 + diff_lane) as usize, setting)
+    })
 }
 
 impl Driver for ShortestDistDriver {
-    fn drive(&self, car: &Car, _ts: f32, setting: &RoundaboutSimSetting) -> Action {
-        let rem_theta = (car.dst / car.pos).to_polar().1.abs(); // remaining
-        if car.finished() {
+    fn name(&self) -> &'static str {
+        "ShortestDist"
+    }
+    fn drive(&self, car: &Car, _ts: Real, setting: &RoundaboutSimSetting) -> Action {
+        if car.finished(setting) {
             // finished
             Action::Stop
-        } else if car.lane > 0 && rem_theta <= THETA_ALLOW {
-            // switch out
+        } else if let Some(action) = exit_action(car, setting) {
+            // switch to an exit lane
+            action
+        } else if let Some(action) = avoid_action(car, setting) {
+            // go around a blocked arc
+            action
+        } else if car.offset > 0.0 && !car.is_on_lane() {
+            // halfway switching out around a blocked arc, continue
             SWITCH_OUT
         } else {
             // greedy
-            // cost for straight then switch out
-            let r0 = setting.r_lanes[0];
+            // cost of driving on lane @i then switching to the nearest exit lane
+            let unwrapped_theta = car.rem_theta();
             let r_curr = setting.r_lanes[car.lane];
-            let unwrapped_theta = unwrap_theta((car.dst / car.pos).arg());
-            // (arc) + (switch out)
-            let straight_dist = (r_curr * unwrapped_theta) + (r0 - r_curr);
-            let switch_in_dist = if car.lane + 1 >= setting.r_lanes.len() {
+            let lane_dist = |i: usize| -> Real {
+                let r_lane = setting.r_lanes[i];
+                let r_exit = setting.r_lanes[setting.nearest_exit_lane(car.arm, i)];
+                // the arc is as long as what the car would drive without the speed limits
+                let arc = car.vel * arc_time(car, i, unwrapped_theta, setting);
+                // (switch to lane) + (arc) + (switch to exit)
+                (r_curr - r_lane).abs() + arc + (r_lane - r_exit).abs()
+            };
+            let straight_dist = lane_dist(car.lane);
+            let switch_in_dist = if car.lane + 1 >= setting.r_lanes.len()
+                || !car.can_switch(1, setting)
+                || !lane_clear(car, car.lane + 1, setting)
+            {
                 // can't switch in
-                f32::INFINITY
+                Real::INFINITY
             } else {
-                // 2 * (switch one in/out) + (inner arc) + (switch from curr to outter most)
-                let r_inner = setting.r_lanes[car.lane + 1];
-                (2.0 * (r_curr - r_inner)) + (r_inner * unwrapped_theta) + (r0 - r_curr)
+                lane_dist(car.lane + 1)
             };
 
             if switch_in_dist < straight_dist && car.lane < setting.r_lanes.len() - 1 {
@@ -91,46 +207,130 @@ impl Driver for ShortestDistDriver {
             }
         }
     }
+    fn next_decision(&self, car: &Car, _ts: Real, setting: &RoundaboutSimSetting) -> Real {
+        // switching ends with an event, stopping ends with other cars' events
+        let Action::Straight = car.action else {
+            return Real::INFINITY;
+        };
+        if setting.incidents.iter().any(|incident| incident.active) {
+            // blocked arcs come closer, sampled like other drivers
+            return setting.tick;
+        }
+        // switching in only gets worse while approaching the destination,
+        // the choice changes when reaching the exit window or leaving a solid line
+        let theta = car.theta();
+        let speed = car.vel.min(setting.speed_limit(car.lane, theta));
+        let omega = speed / setting.r_lanes[car.lane];
+        let mut rem_theta = Real::INFINITY;
+        if !setting.can_exit(car.arm, car.lane) {
+            let diff_lane = if setting.nearest_exit_lane(car.arm, car.lane) < car.lane {
+                -1
+            } else {
+                1
+            };
+            let last_theta = setting.last_switch_theta(car.lane, diff_lane, car.dst_theta());
+            let rem = unwrap_theta(last_theta - car.theta());
+            // aim at the middle of the window, its edge is sensitive to rounding
+            if rem > 0.5 * THETA_ALLOW {
+                rem_theta = rem - 0.5 * THETA_ALLOW;
+            }
+        }
+        for line in &setting.solid_lines {
+            if line.lane == car.lane && in_arc(theta, line.from, line.to) {
+                rem_theta = rem_theta.min((line.to - theta).rem_euclid(2.0 * consts::PI));
+            }
+        }
+        let mut dt = rem_theta / omega;
+        // or past a change of the limits on the inner lane, the engine times those of
+        // this one
+        if car.lane + 1 < setting.r_lanes.len() {
+            let boundary = setting.limit_boundary_theta(car.lane + 1, theta);
+            dt = dt.min(boundary / omega + EVENT_EPS);
+        }
+        dt
+    }
 }
 
 impl Driver for ShortestTimeDriver {
+    fn name(&self) -> &'static str {
+        "ShortestTime"
+    }
+    fn save_state(&self) -> JsonValue {
+        object! {
+            lane_last_ts: reals_to_json(&self.lane_last_ts),
+            lane_last_dist: reals_to_json(&self.lane_last_dist),
+            lane_vel: reals_to_json(&self.lane_vel),
+            prev_lane: self.prev_lane,
+        }
+    }
+    fn load_state(&mut self, jobj: &JsonValue) -> Option<()> {
+        self.lane_last_ts = reals_from_json(&jobj["lane_last_ts"])?;
+        self.lane_last_dist = reals_from_json(&jobj["lane_last_dist"])?;
+        self.lane_vel = reals_from_json(&jobj["lane_vel"])?;
+        self.prev_lane = jobj["prev_lane"].as_usize()?;
+        Some(())
+    }
     fn init(&mut self, car: &Car, setting: &RoundaboutSimSetting) {
         self.lane_last_ts = vec![0.0; setting.r_lanes.len()];
-        self.lane_last_pos = vec![car.pos; setting.r_lanes.len()];
+        self.lane_last_dist = vec![car.dist; setting.r_lanes.len()];
         self.lane_vel = vec![car.vel; setting.r_lanes.len()];
     }
-    fn drive(&self, car: &Car, ts: f32, setting: &RoundaboutSimSetting) -> Action {
+    fn drive(&self, car: &Car, ts: Real, setting: &RoundaboutSimSetting) -> Action {
         /*
          * Choose the lane that gives the earlies arrival time
          * Discard lane vel record that are too long ago
          */
-        if ts - self.lane_last_ts[car.lane] < SHORTEST_TIME_DRIVER_MIN_STAY
-            && is_on_lane(&car.pos, setting.r_lanes[car.lane])
-        {
+        let on_lane = car.is_on_lane();
+        if let Some(action) = exit_action(car, setting) {
+            // a switch towards the exit lane is not turned back halfway
+            let continued = match (action, car.action) {
+                (Action::Switch(diff), Action::Switch(prev)) => diff == prev,
+                _ => false,
+            };
+            if on_lane || continued {
+                return action;
+            }
+        }
+        if let Some(action) = avoid_action(car, setting) {
+            return action;
+        }
+        if ts - self.lane_last_ts[car.lane] < SHORTEST_TIME_DRIVER_MIN_STAY && on_lane {
             return Action::Straight;
         }
-        let mut min_time = f32::INFINITY;
+        let mut min_time = Real::INFINITY;
         let mut min_lane = car.lane;
         for (i, r_lane) in setting.r_lanes.iter().enumerate() {
+            if !setting.classes[car.class].allows_lane(i)
+                || (i != car.lane && !lane_clear(car, i, setting))
+            {
+                continue;
+            }
             let lane_vel = self.lane_vel[i];
-            let unwrapped_theta = unwrap_theta((car.dst / car.pos).arg());
-            let lane_time = r_lane * unwrapped_theta / lane_vel
+            let unwrapped_theta = car.rem_theta();
+            let r_exit = setting.r_lanes[setting.nearest_exit_lane(car.arm, i)];
+            // no faster than the speed limits allow
+            let limited_time = arc_time(car, i, unwrapped_theta, setting);
+            let lane_time = (r_lane * unwrapped_theta / lane_vel).max(limited_time)
                 + (setting.r_lanes[i] - setting.r_lanes[car.lane]).abs() / car.vel
-                + (setting.r_lanes[0] - setting.r_lanes[i]) / car.vel;
+                + (r_exit - setting.r_lanes[i]).abs() / car.vel;
             if lane_time < min_time {
                 min_time = lane_time;
                 min_lane = i;
             }
         }
-        if min_lane < car.lane {
+        let diff_lane = (min_lane as i32 - car.lane as i32).signum();
+        if on_lane && diff_lane != 0 && !car.can_switch(diff_lane, setting) {
+            // solid line, wait for the next chance
+            Action::Straight
+        } else if min_lane < car.lane {
             SWITCH_OUT
         } else if min_lane > car.lane {
             SWITCH_IN
         } else {
             // min_kane == car.lane
-            if is_on_lane(&car.pos, setting.r_lanes[car.lane]) {
+            if on_lane {
                 Action::Straight
-            } else if car.pos.norm() > setting.r_lanes[car.lane] {
+            } else if car.offset > 0.0 {
                 // halfway switching out, continue
                 SWITCH_OUT
             } else {
@@ -138,24 +338,24 @@ impl Driver for ShortestTimeDriver {
             }
         }
     }
-    fn update(&mut self, car: &Car, ts: f32, setting: &RoundaboutSimSetting) {
+    fn update(&mut self, car: &Car, ts: Real, _setting: &RoundaboutSimSetting) {
         let Car {
             lane,
             action,
-            pos,
             vel,
+            dist,
             ..
         } = *car;
         // reset lane record
         if self.prev_lane != car.lane {
             self.prev_lane = lane;
             self.lane_vel[lane] = vel;
-            self.lane_last_pos[lane] = pos;
+            self.lane_last_dist[lane] = dist;
             self.lane_last_ts[lane] = ts;
         }
         // refresh the oldest record
         let mut need_refresh = None;
-        let mut oldest_ts = f32::INFINITY;
+        let mut oldest_ts = Real::INFINITY;
         for (i, last_ts) in self.lane_last_ts.iter_mut().enumerate() {
             if ts - *last_ts > SHORTEST_TIME_DRIVER_REFRESH && i != lane && *last_ts < oldest_ts {
                 oldest_ts = *last_ts;
@@ -171,9 +371,8 @@ impl Driver for ShortestTimeDriver {
         }
         match action {
             Action::Straight | Action::Stop => {
-                self.lane_vel[lane] = setting.r_lanes[lane]
-                    * unwrap_theta((pos / self.lane_last_pos[lane]).arg())
-                    / (ts - self.lane_last_ts[lane]);
+                self.lane_vel[lane] =
+                    (dist - self.lane_last_dist[lane]) / (ts - self.lane_last_ts[lane]);
                 if self.lane_vel[lane] > vel {
                     self.lane_vel[lane] = vel;
                 }
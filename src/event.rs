@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{Real, unwrap_theta};
+use crate::config::EventConfig;
+use crate::{CarState, RoundaboutSim};
+use json::{JsonValue, object};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventAction {
+    Remove,         // taken off the roundabout, like a broken-down car towed away
+    Reroute(usize), // leave at another intersection instead
+    SetVel(Real),   // drive at another desired velocity
+}
+
+/**
+    Change to car @car scripted at time @t, ignored if the car is not on the
+    roundabout then
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub t: Real,
+    pub car: usize,
+    pub action: EventAction,
+}
+
+impl Event {
+    pub fn from_config(config: &EventConfig) -> Event {
+        let (t, car, action) = match *config {
+            EventConfig::Remove { t, car } => (t, car, EventAction::Remove),
+            EventConfig::Reroute { t, car, dst } => (t, car, EventAction::Reroute(dst)),
+            EventConfig::SetVel { t, car, vel } => (t, car, EventAction::SetVel(vel)),
+        };
+        Event { t, car, action }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = object! {
+            t: self.t,
+            car: self.car,
+        };
+        match self.action {
+            EventAction::Remove => jobj["type"] = "Remove".into(),
+            EventAction::Reroute(dst) => {
+                jobj["type"] = "Reroute".into();
+                jobj["dst"] = dst.into();
+            }
+            EventAction::SetVel(vel) => {
+                jobj["type"] = "SetVel".into();
+                jobj["vel"] = vel.into();
+            }
+        }
+        jobj
+    }
+}
+
+impl RoundaboutSim {
+    /**
+        take car @id off the roundabout, it is kept apart from the finished cars.
+        None if it is not on the roundabout
+    */
+    pub fn remove_car(&mut self, id: usize) -> Option<CarState> {
+        let idx = self.cars.iter().position(|car| car.borrow().id == id)?;
+        let car = self.cars.remove(idx);
+        let lane = car.borrow().lane;
+        self.lanes[lane].retain(|other| other.borrow().id != id);
+        self.drivers.remove(&id);
+        car.borrow_mut().t_exit = self.t;
+        println!("Car {id} is removed at time {}", self.t);
+        let state = car.borrow().state(&self.setting);
+        self.removed_cars.push(car);
+        Some(state)
+    }
+    /**
+        make car @id leave at intersection @arm, false if it is not on the roundabout
+        or there is no such intersection
+    */
+    pub fn reroute_car(&mut self, id: usize, arm: usize) -> bool {
+        let Some(car) = self.cars.iter().find(|car| car.borrow().id == id) else {
+            return false;
+        };
+        if arm >= self.setting.n_inter {
+            return false;
+        }
+        let mut car = car.borrow_mut();
+        car.arm = arm;
+        car.dst_dist =
+            car.dist + unwrap_theta(self.setting.inter_theta(arm) - car.theta()) * car.r_lane;
+        true
+    }
+    /**
+        make car @id drive at @vel, reached with the acceleration of its class. False
+        if it is not on the roundabout or @vel is not positive
+    */
+    pub fn set_car_vel(&mut self, id: usize, vel: Real) -> bool {
+        let Some(car) = self.cars.iter().find(|car| car.borrow().id == id) else {
+            return false;
+        };
+        if vel <= 0.0 {
+            return false;
+        }
+        car.borrow_mut().vel = vel;
+        true
+    }
+    /**
+        apply the scripted events that are due, returns true if any car was changed
+    */
+    pub(crate) fn apply_events(&mut self) -> bool {
+        let mut changed = false;
+        while let Some(event) = self.setting.events.get(self.events_done)
+            && event.t <= self.t
+        {
+            let event = event.clone();
+            self.events_done += 1;
+            let applied = match event.action {
+                EventAction::Remove => self.remove_car(event.car).is_some(),
+                EventAction::Reroute(arm) => self.reroute_car(event.car, arm),
+                EventAction::SetVel(vel) => self.set_car_vel(event.car, vel),
+            };
+            if !applied {
+                println!(
+                    "{:?} of car {} at time {} ignored, it is not on the roundabout",
+                    event.action, event.car, event.t
+                );
+            }
+            changed |= applied;
+        }
+        changed
+    }
+}
@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::consts::PI;
+use crate::common::{Real, unwrap_theta};
+use crate::config::ShapeConfig;
+use json::{JsonValue, array, object};
+use num_complex::Complex;
+
+const ELLIPSE_SAMPLES: usize = 720;
+const SPLINE_SAMPLES: usize = 16; // per segment of a smooth polyline
+pub const CURVATURE_SPAN: Real = PI / 32.0; // angle on each side of a point to measure its radius
+
+/**
+    Closed curve of a lane, going around the center from the point at angle 0
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle {
+        r: Real,
+    },
+    // semi-axes @a along x and @b along y
+    Ellipse {
+        a: Real,
+        b: Real,
+    },
+    // through @points in order and back to the first, a Catmull-Rom spline if @smooth
+    Polyline {
+        points: Vec<[Real; 2]>,
+        smooth: bool,
+    },
+}
+
+impl Shape {
+    /**
+        shape of a lane in a configuration, None for a polyline of less than 3 points
+    */
+    pub fn from_config(config: &ShapeConfig) -> Option<Shape> {
+        match config {
+            ShapeConfig::Circle { r } => Some(Shape::Circle { r: *r }),
+            ShapeConfig::Ellipse { a, b } => Some(Shape::Ellipse { a: *a, b: *b }),
+            ShapeConfig::Polyline { points, smooth } => {
+                (points.len() >= 3).then(|| Shape::Polyline {
+                    points: points.clone(),
+                    smooth: smooth.unwrap_or(false),
+                })
+            }
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Shape::Circle { r } => object! {
+                type: "Circle",
+                r: *r,
+            },
+            Shape::Ellipse { a, b } => object! {
+                type: "Ellipse",
+                a: *a,
+                b: *b,
+            },
+            Shape::Polyline { points, smooth } => {
+                let mut jobj = object! {
+                    type: "Polyline",
+                    points: points
+                        .iter()
+                        .map(|[x, y]| array![*x, *y])
+                        .collect::<Vec<_>>(),
+                };
+                if *smooth {
+                    jobj["smooth"] = true.into();
+                }
+                jobj
+            }
+        }
+    }
+}
+
+/**
+    point at @t in [0, 1] of the Catmull-Rom segment from @p1 to @p2
+*/
+fn catmull_rom([p0, p1, p2, p3]: [Complex<Real>; 4], t: Real) -> Complex<Real> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+/**
+    A lane Shape parameterised by arc length
+*/
+#[derive(Debug, Clone)]
+pub struct Curve {
+    pub shape: Shape,
+    points: Vec<Complex<Real>>, // closed polyline through the shape, the first point repeated
+    lengths: Vec<Real>,         // arc length from the first point to each point
+}
+
+impl Curve {
+    pub fn new(shape: Shape) -> Curve {
+        let mut points: Vec<Complex<Real>> = match &shape {
+            Shape::Circle { .. } => vec![],
+            Shape::Ellipse { a, b } => (0..ELLIPSE_SAMPLES)
+                .map(|i| {
+                    let t = 2.0 * PI * (i as Real) / (ELLIPSE_SAMPLES as Real);
+                    Complex::new(a * t.cos(), b * t.sin())
+                })
+                .collect(),
+            Shape::Polyline { points, smooth } => {
+                let points: Vec<Complex<Real>> =
+                    points.iter().map(|[x, y]| Complex::new(*x, *y)).collect();
+                if *smooth {
+                    let n = points.len();
+                    (0..n)
+                        .flat_map(|i| {
+                            let segment = [
+                                points[(i + n - 1) % n],
+                                points[i],
+                                points[(i + 1) % n],
+                                points[(i + 2) % n],
+                            ];
+                            (0..SPLINE_SAMPLES).map(move |k| {
+                                catmull_rom(segment, (k as Real) / (SPLINE_SAMPLES as Real))
+                            })
+                        })
+                        .collect()
+                } else {
+                    points
+                }
+            }
+        };
+        if let Some(first) = points.first() {
+            points.push(*first);
+        }
+        let mut lengths = vec![];
+        let mut length = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                length += (point - points[i - 1]).norm();
+            }
+            lengths.push(length);
+        }
+        Curve {
+            shape,
+            points,
+            lengths,
+        }
+    }
+    pub fn perimeter(&self) -> Real {
+        match self.shape {
+            Shape::Circle { r } => 2.0 * PI * r,
+            _ => *self.lengths.last().unwrap_or(&0.0),
+        }
+    }
+    /**
+        radius of the circle of the same perimeter, distances on the lane are those on
+        that circle
+    */
+    pub fn effective_radius(&self) -> Real {
+        self.perimeter() / (2.0 * PI)
+    }
+    /**
+        radius of curvature at @theta, that of the circle through the points CURVATURE_SPAN
+        before and after it, infinity where the lane is straight
+    */
+    pub fn radius(&self, theta: Real) -> Real {
+        if let Shape::Circle { r } = self.shape {
+            return r;
+        }
+        let a = self.point(theta - CURVATURE_SPAN);
+        let b = self.point(theta);
+        let c = self.point(theta + CURVATURE_SPAN);
+        let cross = ((b - a).conj() * (c - a)).im.abs();
+        if cross > 0.0 {
+            (b - a).norm() * (c - b).norm() * (c - a).norm() / (2.0 * cross)
+        } else {
+            Real::INFINITY
+        }
+    }
+    /**
+        largest distance from the center
+    */
+    pub fn extent(&self) -> Real {
+        match self.shape {
+            Shape::Circle { r } => r,
+            _ => self
+                .points
+                .iter()
+                .fold(0.0, |max, point| point.norm().max(max)),
+        }
+    }
+    /**
+        point at @theta / (2 PI) of the perimeter from the first one
+    */
+    pub fn point(&self, theta: Real) -> Complex<Real> {
+        if let Shape::Circle { r } = self.shape {
+            return Complex::from_polar(r, theta);
+        }
+        let s = unwrap_theta(theta) / (2.0 * PI) * self.perimeter();
+        let i = self
+            .lengths
+            .partition_point(|length| *length <= s)
+            .clamp(1, self.points.len() - 1);
+        let segment = self.lengths[i] - self.lengths[i - 1];
+        let t = if segment > 0.0 {
+            (s - self.lengths[i - 1]) / segment
+        } else {
+            0.0
+        };
+        self.points[i - 1] + (self.points[i] - self.points[i - 1]) * t
+    }
+}
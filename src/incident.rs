@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{Real, in_arc, unwrap_theta};
+use crate::config::IncidentConfig;
+use crate::{RoundaboutSim, RoundaboutSimSetting};
+use json::{JsonValue, object};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncidentKind {
+    // nothing enters the arc of @lane from @from to @to, like a broken-down car or
+    // roadworks
+    LaneBlocked {
+        lane: usize,
+        from: Real,
+        to: Real,
+    },
+    // cars cannot leave at intersection @inter and go another lap
+    ExitClosed {
+        inter: usize,
+    },
+    // cars on the arc of @lane from @from to @to drive at most at @vel
+    SlowZone {
+        lane: usize,
+        from: Real,
+        to: Real,
+        vel: Real,
+    },
+}
+
+/**
+    Incident lasting from @start until @end
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incident {
+    pub start: Real,
+    pub end: Real, // infinity if it never ends
+    pub kind: IncidentKind,
+    pub active: bool, // at the current time, kept by the simulation
+}
+
+impl Incident {
+    pub fn from_config(config: &IncidentConfig) -> Incident {
+        let (kind, start, end) = match *config {
+            IncidentConfig::LaneBlocked {
+                lane,
+                from,
+                to,
+                start,
+                end,
+            } => (IncidentKind::LaneBlocked { lane, from, to }, start, end),
+            IncidentConfig::ExitClosed { inter, start, end } => {
+                (IncidentKind::ExitClosed { inter }, start, end)
+            }
+            IncidentConfig::SlowZone {
+                lane,
+                from,
+                to,
+                vel,
+                start,
+                end,
+            } => (
+                IncidentKind::SlowZone {
+                    lane,
+                    from,
+                    to,
+                    vel,
+                },
+                start,
+                end,
+            ),
+        };
+        Incident {
+            start: start.unwrap_or(0.0),
+            end: end.unwrap_or(Real::INFINITY),
+            kind,
+            active: false,
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = match self.kind {
+            IncidentKind::LaneBlocked { lane, from, to } => object! {
+                type: "LaneBlocked",
+                lane: lane,
+                from: from,
+                to: to,
+            },
+            IncidentKind::ExitClosed { inter } => object! {
+                type: "ExitClosed",
+                inter: inter,
+            },
+            IncidentKind::SlowZone {
+                lane,
+                from,
+                to,
+                vel,
+            } => object! {
+                type: "SlowZone",
+                lane: lane,
+                from: from,
+                to: to,
+                vel: vel,
+            },
+        };
+        jobj["start"] = self.start.into();
+        if self.end.is_finite() {
+            jobj["end"] = self.end.into();
+        }
+        jobj
+    }
+}
+
+impl RoundaboutSimSetting {
+    /**
+        whether the exit of intersection @inter is closed now
+    */
+    pub fn exit_closed(&self, inter: usize) -> bool {
+        self.incidents
+            .iter()
+            .any(|incident| incident.active && incident.kind == IncidentKind::ExitClosed { inter })
+    }
+    /**
+        whether @theta is in a blocked arc of @lane now
+    */
+    pub fn lane_blocked(&self, lane: usize, theta: Real) -> bool {
+        self.incidents.iter().any(|incident| match incident.kind {
+            IncidentKind::LaneBlocked {
+                lane: blocked,
+                from,
+                to,
+            } => incident.active && blocked == lane && in_arc(theta, from, to),
+            _ => false,
+        })
+    }
+    /**
+        angle from @theta to the next blocked arc ahead on @lane, infinity if there is
+        none. Arcs containing @theta are left behind, not ahead
+    */
+    pub fn obstacle_theta(&self, lane: usize, theta: Real) -> Real {
+        self.incidents
+            .iter()
+            .filter_map(|incident| match incident.kind {
+                IncidentKind::LaneBlocked {
+                    lane: blocked,
+                    from,
+                    to,
+                } if incident.active && blocked == lane && !in_arc(theta, from, to) => {
+                    Some(unwrap_theta(from - theta))
+                }
+                _ => None,
+            })
+            .fold(Real::INFINITY, Real::min)
+    }
+    /**
+        the lowest velocity allowed at @theta on @lane by slow zones, infinity outside
+    */
+    pub fn zone_vel(&self, lane: usize, theta: Real) -> Real {
+        self.incidents
+            .iter()
+            .filter_map(|incident| match incident.kind {
+                IncidentKind::SlowZone {
+                    lane: slow,
+                    from,
+                    to,
+                    vel,
+                } if incident.active && slow == lane && in_arc(theta, from, to) => Some(vel),
+                _ => None,
+            })
+            .fold(Real::INFINITY, Real::min)
+    }
+}
+
+impl RoundaboutSim {
+    /**
+        start and end the incidents due
+    */
+    pub(crate) fn update_incidents(&mut self) {
+        for (i, incident) in self.setting.incidents.iter_mut().enumerate() {
+            let active = incident.start <= self.t && self.t < incident.end;
+            if active != incident.active {
+                incident.active = active;
+                println!(
+                    "Incident {i} ({:?}) {} at time {}",
+                    incident.kind,
+                    if active { "starts" } else { "ends" },
+                    self.t
+                );
+            }
+        }
+    }
+    /**
+        time until the next incident starts or ends, infinity if none will
+    */
+    pub(crate) fn next_incident(&self) -> Real {
+        self.setting
+            .incidents
+            .iter()
+            .flat_map(|incident| [incident.start, incident.end])
+            .filter(|t| *t > self.t)
+            .fold(Real::INFINITY, Real::min)
+            - self.t
+    }
+}
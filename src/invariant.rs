@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{Real, unwrap_theta, wrap_theta};
+use crate::{Car, RoundaboutSim};
+use std::collections::HashMap;
+use std::fmt;
+
+const CHECK_ALLOW: Real = 1e-3; // rounding tolerated by the checks
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViolationKind {
+    StraightWhileSwitching, // a car between two lanes is going straight
+    Overlap,                // two cars on the same lane overlap
+    OffLane,                // a car is neither on a lane nor between two adjacent ones
+    PassedThrough,          // a car passed through the car in front of it
+    Backwards,              // a car moved away from its destination
+}
+
+/**
+    A broken invariant, found in step @step (counted from 1) at time @t
+*/
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub step: usize,
+    pub t: Real,
+    pub kind: ViolationKind,
+    pub cars: Vec<usize>, // ids of the cars involved
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "step {} (time {}): {:?} of cars {:?}",
+            self.step, self.t, self.kind, self.cars
+        )
+    }
+}
+
+/**
+    What the checks need to know about the cars before a step
+*/
+pub(crate) struct StepStart {
+    cars: HashMap<usize, (usize, Real, Real)>, // lane, theta and angle left by id
+    followers: Vec<(usize, usize)>,            // each car on a lane and the one in front
+}
+
+/**
+    consecutive pairs (follower, leader, whether the pair wraps around) of the cars
+    that are on @same_lane
+*/
+fn on_lane_pairs<'a>(same_lane: impl Iterator<Item = &'a Car>) -> Vec<(&'a Car, &'a Car, bool)> {
+    let on_lane: Vec<&Car> = same_lane.filter(|car| car.is_on_lane()).collect();
+    if on_lane.len() < 2 {
+        return vec![];
+    }
+    let n = on_lane.len();
+    (0..n)
+        .map(|i| (on_lane[i], on_lane[(i + 1) % n], i + 1 == n))
+        .collect()
+}
+
+impl RoundaboutSim {
+    pub(crate) fn step_start(&self) -> StepStart {
+        let mut cars = HashMap::new();
+        for car in &self.cars {
+            let car = car.borrow();
+            cars.insert(car.id, (car.lane, car.theta(), car.rem_theta()));
+        }
+        let mut followers = vec![];
+        for same_lane in &self.lanes {
+            let same_lane: Vec<_> = same_lane.iter().map(|car| car.borrow()).collect();
+            for (follow, precede, _) in on_lane_pairs(same_lane.iter().map(|car| &**car)) {
+                followers.push((follow.id, precede.id));
+            }
+        }
+        StepStart { cars, followers }
+    }
+    /**
+        invariants broken by the step that started at @start
+    */
+    pub(crate) fn check_step(&self, start: &StepStart) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut violate = |kind: ViolationKind, cars: Vec<usize>| {
+            violations.push(Violation {
+                step: self.step,
+                t: self.t,
+                kind,
+                cars,
+            });
+        };
+        let setting = &self.setting;
+        let r_lanes = &setting.r_lanes;
+        let cars: Vec<_> = self.cars.iter().map(|car| car.borrow()).collect();
+        let by_id: HashMap<usize, &Car> = cars.iter().map(|car| (car.id, &**car)).collect();
+        for car in &cars {
+            // outward offsets point to the lane before, inward ones to the lane after
+            let between = if car.offset > 0.0 {
+                car.lane > 0 && car.offset < r_lanes[car.lane - 1] - r_lanes[car.lane]
+            } else {
+                car.lane + 1 < r_lanes.len()
+                    && car.offset > r_lanes[car.lane + 1] - r_lanes[car.lane]
+            };
+            if !(car.is_on_lane() || between) || !car.dist.is_finite() {
+                violate(ViolationKind::OffLane, vec![car.id]);
+            }
+            let Some((_, theta, rem_theta)) = start.cars.get(&car.id) else {
+                continue;
+            };
+            let advance = wrap_theta(car.theta() - theta);
+            // the remaining angle starts over after missing the exit
+            let lapped = *rem_theta <= advance + CHECK_ALLOW;
+            if advance < -CHECK_ALLOW || (!lapped && car.rem_theta() > rem_theta + CHECK_ALLOW) {
+                violate(ViolationKind::Backwards, vec![car.id]);
+            }
+        }
+        for same_lane in &self.lanes {
+            let same_lane: Vec<_> = same_lane.iter().map(|car| car.borrow()).collect();
+            for (follow, precede, wraps) in on_lane_pairs(same_lane.iter().map(|car| &**car)) {
+                if self.straight_gap(follow, precede, wraps) < -CHECK_ALLOW {
+                    violate(ViolationKind::Overlap, vec![follow.id, precede.id]);
+                }
+            }
+        }
+        for (follow, precede) in &start.followers {
+            let (Some(follow), Some(precede)) = (by_id.get(follow), by_id.get(precede)) else {
+                continue;
+            };
+            let (Some(follow_start), Some(precede_start)) =
+                (start.cars.get(&follow.id), start.cars.get(&precede.id))
+            else {
+                continue;
+            };
+            if follow.lane != follow_start.0 || precede.lane != precede_start.0 {
+                continue;
+            }
+            let gap = unwrap_theta(precede_start.1 - follow_start.1);
+            if gap == 0.0 {
+                // cars side by side have no order
+                continue;
+            }
+            let follow_advance = unwrap_theta(follow.theta() - follow_start.1);
+            let precede_advance = unwrap_theta(precede.theta() - precede_start.1);
+            if follow_advance > gap + precede_advance + CHECK_ALLOW {
+                violate(ViolationKind::PassedThrough, vec![follow.id, precede.id]);
+            }
+        }
+        violations
+    }
+}
@@ -7,36 +7,154 @@ use json::JsonValue;
 use num_complex::Complex;
 use ordered_float::OrderedFloat;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::f32::consts::PI;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::rc::Rc;
 
+pub mod builder;
 mod common;
+pub mod config;
+pub mod crossing;
+pub mod demand;
 pub mod drivers;
+pub mod event;
+pub mod geometry;
+pub mod incident;
+pub mod invariant;
+pub mod network;
+mod query;
+pub mod run;
 pub mod setting;
+pub mod signal;
+mod snapshot;
+pub mod stats;
+pub mod trajectory;
+pub mod validate;
+pub mod vehicle;
 
-use common::{Action, Shared, THETA_ALLOW, unwrap_theta};
+pub use builder::RoundaboutSimBuilder;
+use common::consts::PI;
+pub use common::{Action, Real, consts};
+use common::{Shared, THETA_ALLOW, unwrap_theta, wrap_theta};
+pub use config::{Config, Format};
+pub use crossing::Crossing;
+use demand::Arrival;
+pub use demand::{Demand, Interpolation, Profile};
 pub use drivers::{Driver, DriverFactory};
+pub use event::{Event, EventAction};
+pub use geometry::{Curve, Shape};
+pub use incident::{Incident, IncidentKind};
+pub use invariant::{Violation, ViolationKind};
+pub use network::{Link, RoundaboutNetwork, Trip, network_run};
+pub use query::CarState;
+pub use run::{StopCondition, StopReason, run_file};
 pub use setting::RoundaboutSimSetting;
-use setting::SwitchPolicy;
+pub use setting::{Circulation, Engine, SwitchPolicy};
+pub use signal::{Signal, SignalState};
+pub use stats::{BinStatistics, Statistics};
+pub use trajectory::Trajectory;
+pub use validate::{Problem, validate};
+pub use vehicle::VehicleClass;
 
-const DIST_ALLOW: f32 = 1e-2;
-const MIN_UPDATE_TICK: f32 = 1e-2;
+const DIST_ALLOW: Real = 1e-2;
+const MIN_UPDATE_TICK: Real = 1e-2;
+const ENTRY_GAP: Real = 5.0 * DIST_ALLOW; // free arc required around an entrance
+pub(crate) const EVENT_EPS: Real = 1e-4; // overshoot so that arrivals and switches complete at an event
 
 #[derive(Debug)]
 pub struct Car {
     pub id: usize,
-    pos: Complex<f32>, // to tacke polar
-    vel: f32,
-    lane: usize,       // 0 is the outermost
-    dst: Complex<f32>, // destination polar
+    dist: Real,      // arc length along the lane from angle 0, never wrapped
+    r_lane: Real,    // radius of the lane, the angle is dist / r_lane
+    offset: Real,    // radial distance from the lane while switching, > 0 means outwards
+    vel: Real,       // desired velocity
+    speed: Real,     // current velocity, reaches vel with the class acceleration
+    speed_cap: Real, // upper bound of speed in the current step
+    lane: usize,     // 0 is the outermost
+    dst_dist: Real,  // arc length of the destination, in [dist, dist + the lane perimeter)
+    arm: usize,      // destination intersection
+    class: usize,    // index to setting.classes
     action: Action,
+    t_enter: Real, // time entering the roundabout
+    t_exit: Real,  // time leaving the roundabout
 }
 
 impl Car {
-    fn finished(&self) -> bool {
-        self.lane == 0 && (self.dst - self.pos).norm() <= DIST_ALLOW
+    /**
+        a car of @class at its class velocity, entering at time 0
+    */
+    fn new(
+        id: usize,
+        lane: usize,
+        theta: Real,
+        arm: usize,
+        class: usize,
+        setting: &RoundaboutSimSetting,
+    ) -> Car {
+        let vel = setting.classes[class].vel;
+        let r_lane = setting.r_lanes[lane];
+        let theta = unwrap_theta(theta);
+        Car {
+            id,
+            dist: theta * r_lane,
+            r_lane,
+            offset: 0.0,
+            vel,
+            speed: vel,
+            speed_cap: Real::INFINITY,
+            lane,
+            dst_dist: (theta + unwrap_theta(setting.inter_theta(arm) - theta)) * r_lane,
+            arm,
+            class,
+            action: Action::Straight,
+            t_enter: 0.0,
+            t_exit: Real::NAN,
+        }
+    }
+    /**
+        position in the plane
+    */
+    pub fn pos(&self, setting: &RoundaboutSimSetting) -> Complex<Real> {
+        setting.world_pos(self.lane, self.offset, self.theta())
+    }
+    /**
+        distance from the center
+    */
+    fn r(&self, setting: &RoundaboutSimSetting) -> Real {
+        setting.r_lanes[self.lane] + self.offset
+    }
+    /**
+        angle on the roundabout in [0, 2 PI), along the circulation
+    */
+    fn theta(&self) -> Real {
+        unwrap_theta(self.dist / self.r_lane)
+    }
+    /**
+        angle of the destination in [0, 2 PI)
+    */
+    fn dst_theta(&self) -> Real {
+        unwrap_theta(self.dst_dist / self.r_lane)
+    }
+    /**
+        angle in (-PI, PI], cars on a lane are ordered by it
+    */
+    fn angle(&self) -> Real {
+        wrap_theta(self.dist / self.r_lane)
+    }
+    /**
+        angle left to the destination in [0, 2 PI)
+    */
+    fn rem_theta(&self) -> Real {
+        (self.dst_dist - self.dist) / self.r_lane
+    }
+    fn is_on_lane(&self) -> bool {
+        is_on_lane(self.offset)
+    }
+    fn finished(&self, setting: &RoundaboutSimSetting) -> bool {
+        // the destination may be just behind
+        let rem = self.dst_dist - self.dist;
+        let arc = rem.min(2.0 * PI * self.r_lane - rem);
+        setting.can_exit(self.arm, self.lane) && self.offset.hypot(arc) <= DIST_ALLOW
     }
     /**
         called when action is granted
@@ -44,32 +162,59 @@ impl Car {
     fn set_action(&mut self, action: Action) {
         self.action = action;
     }
+    /**
+        whether switching by @diff_lane is allowed by lane dividers and the vehicle class
+    */
+    fn can_switch(&self, diff_lane: i32, setting: &RoundaboutSimSetting) -> bool {
+        setting.switch_allowed(self.lane, diff_lane, self.theta())
+            && setting.classes[self.class].allows_lane((self.lane as i32 + diff_lane) as usize)
+    }
     /**
         update according to verified action
     */
-    fn update(&mut self, tick: f32, setting: &RoundaboutSimSetting) {
+    fn update(&mut self, tick: Real, setting: &RoundaboutSimSetting) {
+        match self.action {
+            Action::Stop => self.speed = 0.0,
+            _ => {
+                let accel = setting.classes[self.class].accel;
+                self.speed = (self.speed + accel * tick)
+                    .min(self.vel)
+                    .min(self.speed_cap)
+                    .min(setting.speed_limit(self.lane, self.theta()));
+            }
+        }
         match self.action {
             Action::Switch(ref diff_lane) => {
-                let next_r = self.pos.norm() + ((-diff_lane as f32) * self.vel * tick);
-                let target_r = setting.r_lanes[((self.lane as i32) + diff_lane) as usize];
+                let next_r = self.r(setting) + ((-diff_lane as Real) * self.speed * tick);
+                let target_lane = ((self.lane as i32) + diff_lane) as usize;
+                let target_r = setting.r_lanes[target_lane];
                 if *diff_lane < 0 && next_r >= target_r || /* switch out */
                    *diff_lane > 0 && next_r <= target_r
                 /* switch in */
                 {
-                    self.pos = Complex::from_polar(target_r, self.pos.arg());
-                    self.lane = ((self.lane as i32) + diff_lane) as usize;
+                    // the same angle on the other lane
+                    let ratio = target_r / self.r_lane;
+                    self.dist *= ratio;
+                    self.dst_dist *= ratio;
+                    self.r_lane = target_r;
+                    self.lane = target_lane;
+                    self.offset = 0.0;
                 } else {
-                    self.pos = Complex::from_polar(next_r, self.pos.arg());
+                    self.offset = next_r - setting.r_lanes[self.lane];
                 }
             }
             Action::Straight => {
-                let mv = Complex::from_polar(1.0, (self.vel * tick) / setting.r_lanes[self.lane]);
-                let next_pos = self.pos * mv;
-                if (next_pos / self.dst).arg() > 0.0 && (self.pos / self.dst).arg() < 0.0 {
-                    // cross the dst
-                    self.pos = Complex::from_polar(setting.r_lanes[self.lane], self.dst.arg());
+                let next_dist = self.dist + self.speed * tick;
+                let open = !setting.exit_closed(self.arm);
+                if open && self.dist < self.dst_dist && next_dist > self.dst_dist {
+                    // cross the dst, a closed exit is passed without losing ground
+                    self.dist = self.dst_dist;
                 } else {
-                    self.pos = next_pos;
+                    self.dist = next_dist;
+                }
+                if self.dist > self.dst_dist {
+                    // missed the exit, the destination comes again after a lap
+                    self.dst_dist += 2.0 * PI * self.r_lane;
                 }
             }
             Action::Stop => {}
@@ -78,129 +223,504 @@ impl Car {
 }
 
 pub struct RoundaboutSim {
-    pub t: f32, // current time,
+    pub t: Real,     // current time,
+    pub step: usize, // number of updates so far
     pub setting: RoundaboutSimSetting,
     pub finished_cars: Vec<Shared<Car>>,
     cars: Vec<Shared<Car>>,
-    drivers: Vec<Box<dyn Driver>>,
+    removed_cars: Vec<Shared<Car>>, // taken off the roundabout before finishing
+    lanes: Vec<VecDeque<Shared<Car>>>, // cars on each lane by increasing angle
+    drivers: HashMap<usize, Box<dyn Driver>>, // by car id
+    driver: JsonValue,              // driver of generated cars
+    arrivals: VecDeque<Arrival>,    // not yet arrived
+    queues: Vec<VecDeque<Arrival>>, // waiting to enter at each intersection
+    next_id: usize,
+    blocked: Vec<VecDeque<(Real, Real)>>, // pending blocked intervals of each crossing
+    exit_queues: Vec<VecDeque<Shared<Car>>>, // left the roundabout, waiting at a crossing
+    signal_states: Vec<(SignalState, Real)>, // state of each signal and since when
+    green_time: Vec<Real>,                // total green time of each signal
+    violations: Vec<Violation>,           // broken invariants, run_until stops at them
+    events_done: usize,                   // scripted events already applied
+    stalled: bool,                        // see RoundaboutSim::is_stalled
 }
 
 impl RoundaboutSim {
+    /**
+        simulation at time 0 of @setting with the cars of the configuration @jobj
+    */
     pub fn new(setting: RoundaboutSimSetting, jobj: &JsonValue) -> Option<RoundaboutSim> {
-        if !jobj.has_key("init") {
-            return None;
-        }
-        let jinit = &jobj["init"];
-        let jdriver = &jobj["driver"];
+        RoundaboutSim::from_config(setting, &Config::from_json(jobj).ok()?)
+    }
+    /**
+        simulation at time 0 of @setting with the cars of @config, None if a car is
+        on a lane or of a class @setting does not have
+    */
+    pub fn from_config(setting: RoundaboutSimSetting, config: &Config) -> Option<RoundaboutSim> {
+        let jdriver: JsonValue = config.driver.as_deref().into();
         let mut cars = vec![];
-        let mut drivers = vec![];
-        for (key, value) in jinit.entries() {
-            let lane = value["lane"].as_usize()?;
-            let r = *setting.r_lanes.get(lane)?;
-            let theta = value["theta"].as_f32()?;
-            assert!(lane < setting.r_lanes.len());
-            cars.push(Rc::new(RefCell::new(Car {
-                id: key.parse().ok()?,
-                pos: Complex::from_polar(r, theta),
-                vel: value["vel"].as_f32()?,
-                lane,
-                dst: Complex::from_polar(
-                    setting.r_lanes[0],
-                    2.0 * PI / (setting.n_inter as f32) * value["dst"].as_f32()?,
-                ),
-                action: Action::Straight,
-            })));
-            if value.has_key("driver") {
-                drivers.push(DriverFactory::make_boxed_from_json(&value["driver"]));
-            } else {
-                drivers.push(DriverFactory::make_boxed_from_json(jdriver));
+        let mut drivers = HashMap::new();
+        for (id, init) in &config.init {
+            if init.lane >= setting.r_lanes.len() {
+                return None;
             }
+            let class = match &init.class {
+                Some(name) => setting.class_index(name)?,
+                None => 0,
+            };
+            let mut car = Car::new(*id, init.lane, init.theta, init.dst, class, &setting);
+            if let Some(vel) = init.vel {
+                car.vel = vel;
+                car.speed = vel;
+            }
+            cars.push(Rc::new(RefCell::new(car)));
+            let mut driver = match init
+                .driver
+                .as_ref()
+                .or(setting.classes[class].driver.as_ref())
+            {
+                Some(name) => DriverFactory::make_boxed(name),
+                None => DriverFactory::make_boxed_from_json(&jdriver),
+            };
+            driver.init(&cars.last()?.borrow(), &setting);
+            drivers.insert(*id, driver);
         }
-        for (i, driver) in drivers.iter_mut().enumerate() {
-            driver.init(&cars[i].borrow(), &setting);
+        let arrivals = match &setting.demand {
+            Some(demand) => demand.gen_arrivals().into(),
+            None => VecDeque::new(),
+        };
+        let next_id = cars
+            .iter()
+            .map(|car| car.borrow().id + 1)
+            .max()
+            .unwrap_or(0);
+        let blocked = setting
+            .crossings
+            .iter()
+            .map(|crossing| crossing.gen_blocked().into())
+            .collect();
+        let mut lanes = vec![VecDeque::new(); setting.r_lanes.len()];
+        for car in &cars {
+            lanes[car.borrow().lane].push_back(car.clone());
         }
+        for same_lane in &mut lanes {
+            sort_by_angle(same_lane);
+        }
+        let signal_states = setting
+            .signals
+            .iter()
+            .map(|signal| (signal.next_state(0.0, SignalState::Red, 0.0, 0), 0.0))
+            .collect();
         Some(RoundaboutSim {
             t: 0.0,
+            step: 0,
+            green_time: vec![0.0; setting.signals.len()],
+            signal_states,
+            queues: vec![VecDeque::new(); setting.n_inter],
+            exit_queues: vec![VecDeque::new(); setting.n_inter],
+            blocked,
             setting,
             cars,
+            lanes,
             drivers,
+            driver: jdriver,
+            arrivals,
+            next_id,
             finished_cars: vec![],
+            removed_cars: vec![],
+            violations: vec![],
+            events_done: 0,
+            stalled: false,
         })
     }
-    fn from_json(filename: &str) -> Option<RoundaboutSim> {
+    /**
+        travel time statistics by vehicle class
+    */
+    pub fn stats(&self) -> Statistics {
+        Statistics::new(self)
+    }
+    /**
+        number of cars that are not yet on the roundabout
+    */
+    pub fn n_waiting(&self) -> usize {
+        self.arrivals.len() + self.queues.iter().map(|queue| queue.len()).sum::<usize>()
+    }
+    /**
+        number of cars that left the roundabout but wait at a crossing
+    */
+    pub fn n_exiting(&self) -> usize {
+        self.exit_queues.iter().map(|queue| queue.len()).sum()
+    }
+    /**
+        whether a pedestrian is on the crossing of intersection @inter
+    */
+    pub fn is_crossing_blocked(&self, inter: usize) -> bool {
+        self.setting
+            .crossings
+            .iter()
+            .zip(&self.blocked)
+            .any(|(crossing, blocked)| {
+                crossing.inter == inter
+                    && blocked.front().is_some_and(|(start, _)| *start <= self.t)
+            })
+    }
+    /**
+        state of the signal at intersection @inter, None if there is no signal
+    */
+    pub fn signal_state(&self, inter: usize) -> Option<SignalState> {
+        self.setting
+            .signals
+            .iter()
+            .zip(&self.signal_states)
+            .find(|(signal, _)| signal.inter == inter)
+            .map(|(_, (state, _))| *state)
+    }
+    /**
+        total time each signal has been green, same order as setting.signals
+    */
+    pub fn green_time(&self) -> &[Real] {
+        &self.green_time
+    }
+    /**
+        invariants found broken, checked after every step if setting.check_invariants
+    */
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+    /**
+        whether every car stopped in the last step with nothing left to wait for,
+        the cars on the roundabout block each other for good
+    */
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+    /**
+        switch signals according to their control and the queues
+    */
+    fn update_signals(&mut self, tick: Real) {
+        for (i, signal) in self.setting.signals.iter().enumerate() {
+            let (state, since) = self.signal_states[i];
+            if state == SignalState::Green {
+                self.green_time[i] += tick;
+            }
+            let queue = self.queues[signal.inter].len();
+            let next_state = signal.next_state(self.t, state, since, queue);
+            if next_state != state {
+                self.signal_states[i] = (next_state, self.t);
+                println!(
+                    "Signal {i} at intersection {} turns {:?} at time {} after {}",
+                    signal.inter,
+                    next_state,
+                    self.t,
+                    self.t - since
+                );
+            }
+        }
+    }
+    /**
+        drop pedestrians that have crossed and let the cars waiting at free crossings
+        leave, returns true if any car left
+    */
+    fn update_crossings(&mut self) -> bool {
+        for blocked in &mut self.blocked {
+            while let Some((_, end)) = blocked.front()
+                && *end <= self.t
+            {
+                blocked.pop_front();
+            }
+        }
+        let mut left = false;
+        let n_cars = self.finished_cars.len()
+            + self.cars.len()
+            + self.removed_cars.len()
+            + self.n_waiting()
+            + self.n_exiting();
+        for inter in 0..self.setting.n_inter {
+            if self.is_crossing_blocked(inter) {
+                continue;
+            }
+            while let Some(car) = self.exit_queues[inter].pop_front() {
+                car.borrow_mut().t_exit = self.t;
+                self.finished_cars.push(car.clone());
+                left = true;
+                println!(
+                    "Car {} finishes at time {}, ({} / {n_cars})",
+                    car.borrow().id,
+                    self.t,
+                    self.finished_cars.len()
+                );
+            }
+        }
+        left
+    }
+    /**
+        move arrived cars to the queues and let the first car of each queue enter if
+        there is enough space on lane 0, returns true if any car entered
+    */
+    fn enter_arrivals(&mut self) -> bool {
+        while let Some(arrival) = self.arrivals.front()
+            && arrival.t <= self.t
+        {
+            let arrival = self.arrivals.pop_front().unwrap();
+            self.queues[arrival.from].push_back(arrival);
+        }
+        let blocked: Vec<bool> = (0..self.setting.n_inter)
+            .map(|inter| self.is_crossing_blocked(inter))
+            .collect();
+        let setting = &self.setting;
+        let r0 = setting.r_lanes[0];
+        let mut entered = false;
+        let red: Vec<bool> = (0..setting.n_inter)
+            .map(|inter| self.signal_state(inter) == Some(SignalState::Red))
+            .collect();
+        for (inter, queue) in self.queues.iter_mut().enumerate() {
+            let Some(arrival) = queue.front() else {
+                continue;
+            };
+            if blocked[inter] || red[inter] {
+                continue;
+            }
+            let Some(class) = setting.class_index(&arrival.class) else {
+                println!("Unknown vehicle class {}, car dropped", arrival.class);
+                queue.pop_front();
+                continue;
+            };
+            let theta = setting.inter_theta(inter);
+            let id = arrival.id.unwrap_or(self.next_id);
+            let car = Car::new(id, 0, theta, arrival.to, class, setting);
+            let length = setting.classes[class].length;
+            let entering = &self.lanes[0];
+            let n = entering.len();
+            let idx = entering.partition_point(|other| other.borrow().angle() < car.angle());
+            // only cars around the entrance may block it
+            let max_length = setting
+                .classes
+                .iter()
+                .fold(0.0, |max, class| class.length.max(max));
+            let max_vel = entering
+                .iter()
+                .fold(0.0, |max, other| other.borrow().vel.max(max));
+            let reach = ENTRY_GAP + length.max(max_length) + max_vel * setting.tick;
+            let near = |other: &&Shared<Car>| {
+                (wrap_theta(car.theta() - other.borrow().theta()) * r0).abs() < reach
+            };
+            let is_clear = (0..n)
+                .map(|k| &entering[(idx + k) % n])
+                .take_while(near)
+                .chain(
+                    (1..n)
+                        .map(|k| &entering[(idx + n - k) % n])
+                        .take_while(near),
+                )
+                .all(|other| {
+                    let other = other.borrow();
+                    // arc from the other car to the entrance
+                    let ahead = wrap_theta(car.theta() - other.theta()) * r0;
+                    ahead > ENTRY_GAP + length + other.vel * setting.tick
+                        || ahead < -ENTRY_GAP - setting.classes[other.class].length
+                });
+            if !is_clear {
+                continue;
+            }
+            let vel = arrival.vel.unwrap_or(car.vel);
+            let car = Car {
+                vel,
+                speed: vel,
+                t_enter: self.t,
+                ..car
+            };
+            let mut driver = match &setting.classes[class].driver {
+                Some(name) => DriverFactory::make_boxed(name),
+                None => DriverFactory::make_boxed_from_json(&self.driver),
+            };
+            driver.init(&car, setting);
+            self.drivers.insert(car.id, driver);
+            let car = Rc::new(RefCell::new(car));
+            self.cars.push(car.clone());
+            insert_by_angle(&mut self.lanes[0], car);
+            if arrival.id.is_none() {
+                self.next_id += 1;
+            }
+            queue.pop_front();
+            entered = true;
+        }
+        entered
+    }
+    pub fn from_json(filename: &str) -> Option<RoundaboutSim> {
         let contents = fs::read_to_string(filename).expect("File not found");
         let jobj = json::parse(&contents).expect("file format error");
-        let settings = RoundaboutSimSetting::new(&jobj).expect("some required key not specified");
-        let sim = RoundaboutSim::new(settings, &jobj).expect("init cars format error");
-        Some(sim)
+        if jobj.has_key("snapshot") {
+            return RoundaboutSim::restore(&jobj);
+        }
+        match Config::from_json(&jobj) {
+            Ok(config) => RoundaboutSim::build_reported(&config, filename),
+            Err(err) => {
+                println!("{filename}: {err}");
+                None
+            }
+        }
+    }
+    /**
+        simulation of @config read from @filename, printing its problems if it has any
+    */
+    fn build_reported(config: &Config, filename: &str) -> Option<RoundaboutSim> {
+        match config.try_build() {
+            Ok(sim) => Some(sim),
+            Err(problems) => {
+                for problem in problems {
+                    println!("{filename}: {problem}");
+                }
+                None
+            }
+        }
+    }
+    /**
+        simulation from a configuration in any Format, or from a json snapshot
+    */
+    pub fn from_file(filename: &str) -> Option<RoundaboutSim> {
+        if Format::of(filename) == Format::Json {
+            return RoundaboutSim::from_json(filename);
+        }
+        match Config::from_file(filename) {
+            Ok(config) => RoundaboutSim::build_reported(&config, filename),
+            Err(err) => {
+                println!("{err}");
+                None
+            }
+        }
     }
     /**
      * return a bool indicating finished
      */
     pub fn update(&mut self) -> bool {
+        let scripted = self.apply_events();
+        self.update_incidents();
+        let start = self.setting.check_invariants.then(|| self.step_start());
+        let left = self.update_crossings();
+        let entered = self.enter_arrivals();
+        let blocked: Vec<bool> = (0..self.setting.n_inter)
+            .map(|inter| self.is_crossing_blocked(inter))
+            .collect();
+        let spillback: Vec<bool> = (0..self.setting.n_inter)
+            .map(|inter| {
+                self.setting
+                    .crossings
+                    .iter()
+                    .any(|crossing| crossing.inter == inter && crossing.spillback)
+            })
+            .collect();
         let setting = &self.setting;
-        let mut by_lane = HashMap::<usize, Vec<Shared<Car>>>::new();
-        // TODO: Now is O(n lgn)
-        self.cars
-            .sort_by_key(|car| OrderedFloat(car.borrow().pos.arg()));
-        for car in &self.cars {
-            let same_lane = by_lane.entry(car.borrow().lane).or_insert(vec![]);
-            same_lane.push(car.clone());
-        }
         let mut tick = setting.tick;
         // every car determines its action
-        for (i, car) in self.cars.iter().enumerate() {
+        for car in &self.cars {
             let car_ref = &mut car.borrow_mut();
-            let action = { self.drivers[i].drive(car_ref, self.t, setting) };
+            car_ref.speed_cap = Real::INFINITY;
+            let action = { self.drivers[&car_ref.id].drive(car_ref, self.t, setting) };
             car_ref.set_action(action);
         }
+        // cars at a blocked crossing wait on the roundabout
+        for car in &self.cars {
+            let car_ref = &mut car.borrow_mut();
+            if car_ref.finished(setting) && blocked[car_ref.arm] {
+                car_ref.set_action(Action::Stop);
+            }
+        }
+        // switching across a solid line or to a lane not for the class is not allowed
+        for car in &self.cars {
+            let car_ref = &mut car.borrow_mut();
+            if let Action::Switch(diff_lane) = car_ref.action
+                && !car_ref.can_switch(diff_lane, setting)
+            {
+                if car_ref.is_on_lane() {
+                    car_ref.set_action(Action::Straight);
+                } else {
+                    car_ref.set_action(Action::Stop);
+                }
+            }
+        }
         // Staight action while a car is switching is not allowed
         for car in self.cars.iter_mut() {
             let car_ref = &mut car.borrow_mut();
             if let Action::Straight = car_ref.action
-                && !is_on_lane(&car_ref.pos, setting.r_lanes[car_ref.lane])
+                && !car_ref.is_on_lane()
             {
                 car_ref.set_action(Action::Stop);
-                println!(
-                    "Car {}'s action force reset to stop due to straight while switching policy, this is a software bug.",
-                    car_ref.id
-                );
-                return true;
+                let violation = Violation {
+                    step: self.step + 1,
+                    t: self.t,
+                    kind: ViolationKind::StraightWhileSwitching,
+                    cars: vec![car_ref.id],
+                };
+                println!("Invariant violated at {violation}, this is a software bug");
+                self.violations.push(violation);
             }
         }
         // detect straight collision, happens to the same lane
-        let possible_straight_collision = |car_follow: &mut Car, car_precede: &Car| {
-            let time_to_collide = self.straight_collision(car_follow, car_precede);
-            if time_to_collide <= MIN_UPDATE_TICK {
-                car_follow.set_action(Action::Stop);
-                // println!("Car {} makes Car {} stop", car_follow.id, car_precede.id);
-                f32::MAX
-            } else {
-                time_to_collide
-            }
-        };
-        for same_lane in by_lane.values() {
-            for (i, car_follow) in same_lane.iter().enumerate() {
-                if let Some(car_precede) = same_lane.get((i + 1) % same_lane.len())
-                    && same_lane.len() > 1
-                {
-                    let this_tick = possible_straight_collision(
-                        &mut car_follow.borrow_mut(),
-                        &car_precede.borrow(),
-                    );
-                    if this_tick < tick {
-                        tick = this_tick;
-                        // println!("Car {} and Car {} restrict update time to {}", car_follow.borrow().id, car_precede.borrow().id, this_tick)
+        match setting.engine {
+            Engine::Tick => {
+                let possible_straight_collision =
+                    |car_follow: &mut Car, car_precede: &Car, wraps: bool| {
+                        let time_to_collide =
+                            self.straight_collision(car_follow, car_precede, wraps);
+                        if time_to_collide <= MIN_UPDATE_TICK {
+                            car_follow.set_action(Action::Stop);
+                            // println!("Car {} makes Car {} stop", car_follow.id, car_precede.id);
+                            Real::MAX
+                        } else {
+                            time_to_collide
+                        }
+                    };
+                for same_lane in &self.lanes {
+                    for (i, car_follow) in same_lane.iter().enumerate() {
+                        if let Some(car_precede) = same_lane.get((i + 1) % same_lane.len())
+                            && same_lane.len() > 1
+                        {
+                            let this_tick = possible_straight_collision(
+                                &mut car_follow.borrow_mut(),
+                                &car_precede.borrow(),
+                                i + 1 == same_lane.len(),
+                            );
+                            if this_tick < tick {
+                                tick = this_tick;
+                                // println!("Car {} and Car {} restrict update time to {}", car_follow.borrow().id, car_precede.borrow().id, this_tick)
+                            }
+                        }
                     }
                 }
             }
+            Engine::Event => {
+                // cars stopped behind others do not bring events
+                let catch_up = self.resolve_following();
+                tick = self.next_event().min(catch_up);
+            }
+        }
+        // blocked arcs are obstacles to the cars going straight
+        for car in &self.cars {
+            let car_ref = &mut car.borrow_mut();
+            if car_ref.action == Action::Straight {
+                let ahead = setting.obstacle_theta(car_ref.lane, car_ref.theta());
+                // at most the speed Car::update allows in this step
+                let speed = car_ref
+                    .vel
+                    .min(car_ref.speed_cap)
+                    .min(setting.speed_limit(car_ref.lane, car_ref.theta()));
+                let time_to_block = ahead * setting.r_lanes[car_ref.lane] / speed;
+                if time_to_block <= MIN_UPDATE_TICK {
+                    car_ref.set_action(Action::Stop);
+                } else {
+                    // stop short, the start of the arc is part of it
+                    tick = tick.min(time_to_block - 0.5 * MIN_UPDATE_TICK);
+                }
+            }
         }
         // detect switch collision
-        let possbile_switch_collision = |switching_car: &mut Car, car_follow: &mut Car| {
+        let mut switch_clear = Real::INFINITY; // when a stopped switching car may go again
+        let mut possbile_switch_collision = |switching_car: &mut Car, car_follow: &mut Car| {
             if self.switch_collision(switching_car, car_follow, tick) {
                 match setting.switch_policy {
                     SwitchPolicy::StraightFirst => {
                         switching_car.set_action(Action::Stop);
+                        switch_clear =
+                            switch_clear.min(self.switch_pass_time(switching_car, car_follow));
                     }
                     _ => {
                         car_follow.set_action(Action::Stop);
@@ -208,17 +728,16 @@ impl RoundaboutSim {
                 }
             }
         };
-        for (lane, same_lane) in &by_lane {
+        for (lane, same_lane) in self.lanes.iter().enumerate() {
             for car in same_lane {
                 let mut switching_car = car.borrow_mut();
-                let switching_theta = switching_car.pos.arg();
+                let switching_theta = switching_car.angle();
                 if let Action::Switch(diff_lane) = switching_car.action {
-                    let next_lane = (*lane as i32) + diff_lane;
-                    if let Some(other_lane) = by_lane.get(&(next_lane as usize)) {
+                    let next_lane = (lane as i32) + diff_lane;
+                    if let Some(other_lane) = self.lanes.get(next_lane as usize) {
                         // detect from lower bound on other lane
                         let idx = match other_lane.binary_search_by(|probe| {
-                            OrderedFloat(probe.borrow().pos.arg())
-                                .cmp(&OrderedFloat(switching_theta))
+                            OrderedFloat(probe.borrow().angle()).cmp(&OrderedFloat(switching_theta))
                         }) {
                             Ok(i) => i,
                             Err(i) => i,
@@ -229,7 +748,7 @@ impl RoundaboutSim {
                             possbile_switch_collision(&mut switching_car, &mut car_follow);
                         }
                         // detect from max on other lane
-                        if let Some(car_follow) = other_lane.last() {
+                        if let Some(car_follow) = other_lane.back() {
                             let mut car_follow = car_follow.borrow_mut();
                             possbile_switch_collision(&mut switching_car, &mut car_follow);
                         }
@@ -237,32 +756,72 @@ impl RoundaboutSim {
                 }
             }
         }
+        if setting.engine == Engine::Event {
+            tick = tick.min(switch_clear);
+        }
         // detect side collision
         // returns true if car_other falls in the neighborhood of car_center
-        let in_side_coliision_range = |car_center: &Car, car_other: &Car, delta: f32| -> bool {
-            let diff = (car_center.pos / car_other.pos).arg();
+        let in_side_coliision_range = |car_center: &Car, car_other: &Car, delta: Real| -> bool {
+            let diff = wrap_theta(car_center.theta() - car_other.theta());
             diff.abs() < delta
         };
-        let possible_side_collision = |car_center: &Car, car_other: &Car, tick: f32| -> f32 {
+        // radial speed of @car_other if it switches the same way as @car_center
+        let radial_speed = |car_center: &Car, car_other: &Car| -> Real {
+            match (car_center.action, car_other.action) {
+                (Action::Switch(car_diff), Action::Switch(other_diff))
+                    if car_diff == other_diff =>
+                {
+                    car_other.vel.min(car_other.speed_cap)
+                }
+                _ => 0.0,
+            }
+        };
+        let possible_side_collision = |car_center: &Car, car_other: &Car| -> Real {
             if let Action::Switch(car_diff) = car_center.action
                 && let Action::Switch(ref other_diff) = car_other.action
             {
-                let car_r = car_center.pos.norm();
-                let other_r = car_other.pos.norm();
+                let car_r = car_center.r(setting);
+                let other_r = car_other.r(setting);
+                let behind = (car_diff as Real) * (car_r - other_r);
+                // side by side, the car behind on the lane waits for the one ahead
+                let tied = behind == 0.0
+                    && setting.engine == Engine::Event
+                    && wrap_theta(car_other.theta() - car_center.theta()) > 0.0;
                 // both switch in/out, return time to collide if relative position correct
-                return if car_diff == *other_diff && (car_diff as f32) * (car_r - other_r) > 0.0 {
-                    (car_r - other_r).abs() / car_center.vel
+                return if car_diff == *other_diff && (behind > 0.0 || tied) {
+                    match setting.engine {
+                        Engine::Tick => (car_r - other_r).abs() / car_center.vel,
+                        Engine::Event => {
+                            let gap = (car_r - other_r).abs();
+                            let closing = car_center.vel.min(car_center.speed_cap)
+                                - radial_speed(car_center, car_other);
+                            if gap <= MIN_UPDATE_TICK * car_center.vel {
+                                0.0
+                            } else if closing > 0.0 {
+                                // stop right before touching
+                                (gap - 0.5 * MIN_UPDATE_TICK * car_center.vel) / closing
+                            } else {
+                                Real::MAX
+                            }
+                        }
+                    }
                 } else {
-                    tick
+                    Real::MAX
                 };
             }
-            tick
+            Real::MAX
         };
         let detect_side_collision_routine =
-            |car_center: &mut Car, car_other: &Car, tick: f32| -> f32 {
-                let this_tick = possible_side_collision(car_center, car_other, tick);
+            |car_center: &mut Car, car_other: &Car, tick: Real| -> Real {
+                let this_tick = possible_side_collision(car_center, car_other);
                 if this_tick < MIN_UPDATE_TICK {
-                    car_center.set_action(Action::Stop);
+                    let speed = radial_speed(car_center, car_other);
+                    if setting.engine == Engine::Event && speed > 0.0 {
+                        // follow @car_other outwards/inwards
+                        car_center.speed_cap = car_center.speed_cap.min(speed);
+                    } else {
+                        car_center.set_action(Action::Stop);
+                    }
                     // println!("Car {} stops Car {} ", car_other.id, car_center.id);
                     tick
                 } else if this_tick < tick {
@@ -272,7 +831,7 @@ impl RoundaboutSim {
                     tick
                 }
             };
-        for same_lane in by_lane.values() {
+        for same_lane in &self.lanes {
             let n = same_lane.len();
             for (i, car) in same_lane.iter().enumerate() {
                 let car_center = &mut car.borrow_mut();
@@ -300,17 +859,31 @@ impl RoundaboutSim {
                 }
             }
         }
+        if setting.engine == Engine::Event {
+            // cars in front may have been stopped by switching cars
+            tick = tick.min(self.resolve_following());
+        }
         self.t += tick;
-        let mut has_progress = false;
+        self.step += 1;
+        let mut has_progress = entered || left || scripted;
         // TODO: Another chance for changing their actions?
         // update phase
         let mut next_cars = vec![];
-        let n_cars = self.finished_cars.len() + self.cars.len();
-        for (i, car) in self.cars.iter().enumerate() {
+        let mut leaving = vec![]; // with their angle before the update
+        let mut moved = vec![]; // switched lanes or passed theta = PI
+        let n_cars = self.finished_cars.len()
+            + self.cars.len()
+            + self.removed_cars.len()
+            + self.n_waiting()
+            + self.n_exiting();
+        for car in &self.cars {
+            let (theta, lane) = (car.borrow().angle(), car.borrow().lane);
             {
                 let car_ref = &mut car.borrow_mut();
                 car_ref.update(tick, setting);
-                self.drivers[i].update(car_ref, self.t, setting);
+                if let Some(driver) = self.drivers.get_mut(&car_ref.id) {
+                    driver.update(car_ref, self.t, setting);
+                }
             }
             match car.borrow().action {
                 Action::Stop => {}
@@ -318,25 +891,84 @@ impl RoundaboutSim {
                     has_progress = true;
                 }
             };
-            if car.borrow().finished() {
-                self.finished_cars.push(car.clone());
-                has_progress = true;
+            let arm = car.borrow().arm;
+            if car.borrow().finished(setting) && !(blocked[arm] && spillback[arm]) {
+                leaving.push((theta, car.clone()));
+            } else {
+                if car.borrow().lane != lane || car.borrow().angle() < theta - PI {
+                    moved.push(car.clone());
+                }
+                next_cars.push(car.clone());
+            }
+        }
+        // cars leave in the order of their angle
+        leaving.sort_by_key(|(theta, _)| OrderedFloat(*theta));
+        let left_ids: HashSet<usize> = leaving.iter().map(|(_, car)| car.borrow().id).collect();
+        let moved_ids: HashSet<usize> = moved.iter().map(|car| car.borrow().id).collect();
+        for (_, car) in leaving {
+            let arm = car.borrow().arm;
+            self.drivers.remove(&car.borrow().id);
+            has_progress = true;
+            if blocked[arm] {
+                // wait off the roundabout
+                self.exit_queues[arm].push_back(car);
+            } else {
+                car.borrow_mut().t_exit = self.t;
                 println!(
                     "Car {} finishes at time {}, ({} / {n_cars})",
                     car.borrow().id,
                     self.t,
-                    self.finished_cars.len()
+                    self.finished_cars.len() + 1
                 );
-            } else {
-                next_cars.push(car.clone());
+                self.finished_cars.push(car);
             }
         }
         self.cars = next_cars;
-        let all_finished = self.cars.is_empty();
-        assert!(
-            has_progress || all_finished,
-            "everyone stops but not finished"
+        // keep the lanes ordered without sorting them again
+        for (lane, same_lane) in self.lanes.iter_mut().enumerate() {
+            if !left_ids.is_empty() || !moved_ids.is_empty() {
+                same_lane.retain(|car| {
+                    let car = car.borrow();
+                    car.lane == lane && !left_ids.contains(&car.id) && !moved_ids.contains(&car.id)
+                });
+            }
+            resort_by_angle(same_lane);
+        }
+        for car in moved {
+            let lane = car.borrow().lane;
+            insert_by_angle(&mut self.lanes[lane], car);
+        }
+        debug_assert_eq!(
+            self.lanes.iter().map(VecDeque::len).sum::<usize>(),
+            self.cars.len(),
+            "a car is lost by the lanes"
+        );
+        debug_assert!(
+            self.lanes.iter().all(|same_lane| {
+                same_lane
+                    .iter()
+                    .zip(same_lane.iter().skip(1))
+                    .all(|(a, b)| a.borrow().angle() <= b.borrow().angle())
+            }),
+            "lanes out of order"
         );
+        if let Some(start) = start {
+            let violations = self.check_step(&start);
+            for violation in &violations {
+                println!("Invariant violated at {violation}");
+            }
+            self.violations.extend(violations);
+        }
+        self.update_signals(tick);
+        let all_finished = self.cars.is_empty() && self.n_waiting() == 0 && self.n_exiting() == 0;
+        let incident = self
+            .setting
+            .incidents
+            .iter()
+            .any(|incident| incident.active && incident.end.is_finite());
+        // waiting for pedestrians or the end of an incident is not a deadlock
+        self.stalled =
+            !(has_progress || self.cars.is_empty() || blocked.iter().any(|b| *b) || incident);
         if all_finished {
             println!("===== simulation finished in: {} =====", self.t);
         } else {
@@ -349,11 +981,174 @@ impl RoundaboutSim {
         }
         all_finished
     }
+    /**
+        free arc between @car_follow and the rear of @car_precede on the same lane,
+        @wraps if @car_follow is the last car of the lane and @car_precede the first,
+        which is then a lap ahead when the two are level
+    */
+    fn straight_gap(&self, car_follow: &Car, car_precede: &Car, wraps: bool) -> Real {
+        let mut margin_theta = unwrap_theta(car_precede.theta() - car_follow.theta());
+        if wraps && margin_theta == 0.0 {
+            margin_theta = 2.0 * PI;
+        }
+        let length = self.setting.classes[car_precede.class].length;
+        margin_theta * self.setting.r_lanes[car_follow.lane] - length
+    }
+    /**
+        time for the rear of @car_other to pass the point @car_switch is switching to
+    */
+    fn switch_pass_time(&self, car_switch: &Car, car_other: &Car) -> Real {
+        let r_lane = self.setting.r_lanes[car_other.lane];
+        let arc = unwrap_theta(car_switch.theta() - car_other.theta()) * r_lane;
+        let length = self.setting.classes[car_other.class].length;
+        (arc + length) / car_other.vel + EVENT_EPS
+    }
+    /**
+        Event engine: cars right behind a moving car follow it at its speed and those
+        behind a still one stop. Returns the earliest time a car catches up with the
+        one in front of it
+    */
+    fn resolve_following(&self) -> Real {
+        let effective_speed = |car: &Car| -> Real {
+            match car.action {
+                Action::Straight => car.vel.min(car.speed_cap),
+                _ => 0.0,
+            }
+        };
+        for same_lane in &self.lanes {
+            let n = same_lane.len();
+            if n < 2 {
+                continue;
+            }
+            // whether the @i-th car is right behind the next one
+            let follows = |i: usize| -> bool {
+                let car_follow = same_lane[i].borrow();
+                matches!(car_follow.action, Action::Straight)
+                    && self.straight_gap(&car_follow, &same_lane[(i + 1) % n].borrow(), i + 1 == n)
+                        <= MIN_UPDATE_TICK * car_follow.vel
+            };
+            let Some(head) = (0..n).rev().find(|i| !follows(*i)) else {
+                // a platoon all around the lane goes at the pace of its slowest car
+                let speed = same_lane
+                    .iter()
+                    .map(|car| effective_speed(&car.borrow()))
+                    .fold(Real::INFINITY, Real::min);
+                for car in same_lane {
+                    let mut car = car.borrow_mut();
+                    if speed <= 0.0 {
+                        car.set_action(Action::Stop);
+                    } else if speed < effective_speed(&car) {
+                        car.speed_cap = speed;
+                    }
+                }
+                continue;
+            };
+            // a platoon settles from its head backwards, once around from the car
+            // behind one that follows nobody
+            for k in 1..n {
+                let i = (head + n - k) % n;
+                if !follows(i) {
+                    continue;
+                }
+                let mut car_follow = same_lane[i].borrow_mut();
+                let speed = effective_speed(&same_lane[(i + 1) % n].borrow());
+                if speed <= 0.0 {
+                    car_follow.set_action(Action::Stop);
+                } else if speed < effective_speed(&car_follow) {
+                    car_follow.speed_cap = speed;
+                }
+            }
+        }
+        let mut catch_up = Real::INFINITY;
+        for same_lane in &self.lanes {
+            let n = same_lane.len();
+            if n < 2 {
+                continue;
+            }
+            for (i, car) in same_lane.iter().enumerate() {
+                let car_follow = car.borrow();
+                let car_precede = same_lane[(i + 1) % n].borrow();
+                let gap = self.straight_gap(&car_follow, &car_precede, i + 1 == n);
+                let closing = effective_speed(&car_follow) - effective_speed(&car_precede);
+                if closing > 0.0 && gap > MIN_UPDATE_TICK * car_follow.vel {
+                    // stop right before touching
+                    catch_up =
+                        catch_up.min((gap - 0.5 * MIN_UPDATE_TICK * car_follow.vel) / closing);
+                }
+            }
+        }
+        catch_up
+    }
+    /**
+        Event engine: time until the next interaction given the actions of this step
+    */
+    fn next_event(&self) -> Real {
+        let setting = &self.setting;
+        let mut dt = Real::INFINITY;
+        for car in &self.cars {
+            let car = car.borrow();
+            let accel = setting.classes[car.class].accel;
+            let limit = setting.speed_limit(car.lane, car.theta());
+            if car.speed < car.vel.min(limit) && accel.is_finite() {
+                // accelerating cars are sampled every tick
+                dt = dt.min(setting.tick);
+            }
+            match car.action {
+                Action::Straight => {
+                    let speed = car.vel.min(limit).min(car.speed_cap);
+                    // arrival, a lap later for a car leaving its missed destination
+                    let rem_theta = car.rem_theta();
+                    let theta = if rem_theta > EVENT_EPS {
+                        rem_theta
+                    } else {
+                        2.0 * PI
+                    };
+                    dt = dt.min(theta * setting.r_lanes[car.lane] / speed + EVENT_EPS);
+                    // the limit changes past the boundary of a segment
+                    let boundary = setting.limit_boundary_theta(car.lane, car.theta());
+                    dt = dt.min(boundary * setting.r_lanes[car.lane] / speed + EVENT_EPS);
+                }
+                Action::Switch(diff_lane) => {
+                    // switch completion
+                    let target_r = setting.r_lanes[(car.lane as i32 + diff_lane) as usize];
+                    dt = dt.min((car.r(setting) - target_r).abs() / car.vel + EVENT_EPS);
+                }
+                Action::Stop => {}
+            }
+            if let Some(driver) = self.drivers.get(&car.id) {
+                dt = dt.min(driver.next_decision(&car, self.t, setting));
+            }
+        }
+        if let Some(arrival) = self.arrivals.front() {
+            dt = dt.min(arrival.t - self.t);
+        }
+        if let Some(event) = setting.events.get(self.events_done) {
+            dt = dt.min(event.t - self.t);
+        }
+        dt = dt.min(self.next_incident());
+        if self.queues.iter().any(|queue| !queue.is_empty()) {
+            // entrances are checked every tick
+            dt = dt.min(setting.tick);
+        }
+        for blocked in &self.blocked {
+            if let Some((start, end)) = blocked.front() {
+                dt = dt.min(if *start > self.t { start } else { end } - self.t);
+            }
+        }
+        for (signal, (state, since)) in setting.signals.iter().zip(&self.signal_states) {
+            dt = dt.min(signal.next_change(self.t, *state, *since) - self.t);
+        }
+        if dt.is_finite() {
+            dt.max(EVENT_EPS)
+        } else {
+            setting.tick
+        }
+    }
     /**
         Collision if @car_switch is switch in/out to the @car_other.lane and
         @car_other.polar.theta is in the arc occupied by @car_other with time @tick
     */
-    fn switch_collision(&self, car_switch: &Car, car_other: &Car, tick: f32) -> bool {
+    fn switch_collision(&self, car_switch: &Car, car_other: &Car, tick: Real) -> bool {
         if let Action::Straight = car_other.action {
             let setting = &self.setting;
             match car_switch.action {
@@ -363,17 +1158,15 @@ impl RoundaboutSim {
                         return false;
                     }
                     let r_lane = setting.r_lanes[lane];
-                    let switch_target_pos = Complex::from_polar(r_lane, car_switch.pos.arg());
-                    let other_curr_pos = Complex::from_polar(r_lane, car_other.pos.arg());
-                    let other_target_pos = Complex::from_polar(
-                        r_lane,
-                        car_other.pos.arg() + car_other.vel * tick / r_lane,
-                    );
+                    // the arc swept by @car_other, extended by both lengths
+                    let other_length = setting.classes[car_other.class].length;
+                    let switch_length = setting.classes[car_switch.class].length;
+                    let other_curr_theta = car_other.theta() - other_length / r_lane;
+                    let other_target_theta =
+                        car_other.theta() + (car_other.vel * tick + switch_length) / r_lane;
                     // @car_switch is in the arc of @car_other
-                    let other_curr_2_swtich_target = switch_target_pos / other_curr_pos;
-                    let switch_target_2_other_target = other_target_pos / switch_target_pos;
-                    other_curr_2_swtich_target.arg() >= 0.0
-                        && switch_target_2_other_target.arg() <= 0.0
+                    wrap_theta(car_switch.theta() - other_curr_theta) >= 0.0
+                        && wrap_theta(other_target_theta - car_switch.theta()) <= 0.0
                 }
                 _ => false,
             }
@@ -386,7 +1179,7 @@ impl RoundaboutSim {
         assuming @car_precede stays still, and @car_follow take straight action
         Check return value <= 0 as a signal to update @car_follow or not
     */
-    fn straight_collision(&self, car_follow: &Car, car_precede: &Car) -> f32 {
+    fn straight_collision(&self, car_follow: &Car, car_precede: &Car, wraps: bool) -> Real {
         match car_follow.action {
             Action::Straight => {
                 assert_eq!(
@@ -394,20 +1187,58 @@ impl RoundaboutSim {
                     "on the same lane but straight-straight collision called",
                 );
                 assert_ne!(car_follow.id, car_precede.id, "have the same id");
-                let margin_theta = unwrap_theta((car_precede.pos.fdiv(car_follow.pos)).arg());
-                let lane = car_follow.lane;
-                margin_theta * self.setting.r_lanes[lane] / car_follow.vel
+                self.straight_gap(car_follow, car_precede, wraps) / car_follow.vel
             }
-            _ => f32::MAX,
+            _ => Real::MAX,
+        }
+    }
+}
+
+/**
+    sort @same_lane by increasing angle
+*/
+fn sort_by_angle(same_lane: &mut VecDeque<Shared<Car>>) {
+    same_lane
+        .make_contiguous()
+        .sort_by_key(|car| OrderedFloat(car.borrow().angle()));
+}
+
+/**
+    restore the angular order of @same_lane after its cars advanced, linear unless
+    many cars passed each other
+*/
+fn resort_by_angle(same_lane: &mut VecDeque<Shared<Car>>) {
+    let cars = same_lane.make_contiguous();
+    for i in 1..cars.len() {
+        let theta = cars[i].borrow().angle();
+        let mut j = i;
+        while j > 0 && cars[j - 1].borrow().angle() > theta {
+            j -= 1;
         }
+        cars[j..=i].rotate_right(1);
     }
 }
 
-pub fn sim_run(filename: &str, max_t: f32) -> Option<RoundaboutSim> {
-    let mut sim = RoundaboutSim::from_json(filename)?;
-    let mut finished = false;
-    while (sim.t < max_t || max_t < 0.0) && !finished {
-        finished |= sim.update();
+/**
+    insert @car into @same_lane sorted by increasing angle, after the cars at the same angle
+*/
+fn insert_by_angle(same_lane: &mut VecDeque<Shared<Car>>, car: Shared<Car>) {
+    let theta = car.borrow().angle();
+    let idx = same_lane.partition_point(|other| other.borrow().angle() <= theta);
+    same_lane.insert(idx, car);
+}
+
+/**
+    the simulation of @filename once every car finished before @max_t (never stopping
+    if negative), see run_file to keep unfinished ones
+*/
+pub fn sim_run(filename: &str, max_t: Real) -> Option<RoundaboutSim> {
+    let mut conditions = vec![];
+    if max_t >= 0.0 {
+        conditions.push(StopCondition::Time(max_t));
+    }
+    match run_file(filename, &mut conditions)? {
+        (sim, StopReason::AllFinished) => Some(sim),
+        _ => None,
     }
-    if finished { Some(sim) } else { None }
 }
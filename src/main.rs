@@ -1,5 +1,4 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
-use macroquad::prelude::*;
 use roundabout_sim::*;
 use std::env;
 
@@ -20,7 +19,7 @@ async fn main() {
         let n_dst = args[3].parse::<usize>().expect("expect usize");
         let mut r_lanes = vec![];
         for arg in args.iter().skip(4) {
-            r_lanes.push(arg.parse::<f32>().expect("expect f32"));
+            r_lanes.push(arg.parse::<Real>().expect("expect a number"));
         }
         let jobj = RoundaboutSimSetting::gen_random(n_cars, n_dst, &r_lanes);
         println!("{}", json::stringify(jobj));
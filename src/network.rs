@@ -0,0 +1,400 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{AsReal, Real};
+use crate::config::{Config, read_json};
+use crate::demand::Arrival;
+use crate::{RoundaboutSim, Statistics};
+use json::{JsonValue, array, object};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/**
+    Straight road from the exit of a roundabout to the entrance of another one
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub from: (usize, usize), // (ring, intersection) cars leave at
+    pub to: (usize, usize),   // (ring, intersection) cars enter at
+    pub travel_time: Real,    // from one end to the other
+    pub capacity: usize, // cars on the link at once, queuing to enter included, usize::MAX if unlimited
+}
+
+impl Link {
+    pub fn new(jobj: &JsonValue) -> Option<Link> {
+        Some(Link {
+            from: (jobj["from"][0].as_usize()?, jobj["from"][1].as_usize()?),
+            to: (jobj["to"][0].as_usize()?, jobj["to"][1].as_usize()?),
+            travel_time: jobj["travel_time"].as_real()?,
+            capacity: jobj["capacity"].as_usize().unwrap_or(usize::MAX),
+        })
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = object! {
+            from: array![self.from.0, self.from.1],
+            to: array![self.to.0, self.to.1],
+            travel_time: self.travel_time,
+        };
+        if self.capacity != usize::MAX {
+            jobj["capacity"] = self.capacity.into();
+        }
+        jobj
+    }
+}
+
+/**
+    Car entering the network at time @t, its route is the exit taken on each
+    roundabout in turn, the links from those exits lead to the next ones
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trip {
+    pub t: Real,
+    pub ring: usize,       // first roundabout
+    pub from: usize,       // entrance of the first roundabout
+    pub exits: Vec<usize>, // leaves the network at the last one
+    pub vel: Option<Real>, // the class velocity if None
+    pub class: String,
+}
+
+impl Trip {
+    pub fn new(jobj: &JsonValue) -> Option<Trip> {
+        let mut exits = vec![];
+        for exit in jobj["exits"].members() {
+            exits.push(exit.as_usize()?);
+        }
+        (!exits.is_empty()).then_some(())?;
+        Some(Trip {
+            t: jobj["t"].as_real()?,
+            ring: jobj["ring"].as_usize()?,
+            from: jobj["from"].as_usize()?,
+            exits,
+            vel: jobj["vel"].as_real(),
+            class: jobj["class"].as_str().unwrap_or("car").to_string(),
+        })
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = object! {
+            t: self.t,
+            ring: self.ring,
+            from: self.from,
+            exits: self.exits.clone(),
+            class: self.class.as_str(),
+        };
+        if let Some(vel) = self.vel {
+            jobj["vel"] = vel.into();
+        }
+        jobj
+    }
+}
+
+/**
+    Roundabouts joined by links, stepped together so that none gets ahead of the
+    others by more than a step. Each car of a trip keeps its id, the trip index after
+    the ids of the cars initially on the roundabouts, through the whole network
+*/
+pub struct RoundaboutNetwork {
+    pub t: Real, // time of the roundabout furthest behind
+    pub rings: Vec<RoundaboutSim>,
+    pub links: Vec<Link>,
+    pub trips: Vec<Trip>,
+    pub t_done: Vec<Option<Real>>, // time each trip left the network, None until then
+    first_id: usize,               // id of the car of trip 0
+    legs: HashMap<usize, usize>,   // index in the exits of each car on a roundabout
+    on_links: HashMap<usize, usize>, // link each car leaving a roundabout is on
+    link_queues: Vec<VecDeque<(usize, Real)>>, // trips waiting for each full link, and since when
+    link_free: Vec<Real>,          // time the last car left each link
+    seen: Vec<(usize, usize)>,     // finished and removed cars of each ring already handled
+}
+
+impl RoundaboutNetwork {
+    /**
+        network at time 0, None if a roundabout cannot be built or a trip takes an
+        exit without a link while it has exits left
+    */
+    pub fn new(jobj: &JsonValue) -> Option<RoundaboutNetwork> {
+        let mut rings = vec![];
+        for ring in jobj["rings"].members() {
+            rings.push(Config::from_json(ring).ok()?.build()?);
+        }
+        let mut links = vec![];
+        for link in jobj["links"].members() {
+            links.push(Link::new(link)?);
+        }
+        let mut trips = vec![];
+        for trip in jobj["trips"].members() {
+            trips.push(Trip::new(trip)?);
+        }
+        let n_inters: Vec<usize> = rings.iter().map(|ring| ring.setting.n_inter).collect();
+        let n_inter = |ring: usize| n_inters.get(ring).copied();
+        for link in &links {
+            if link.from.1 >= n_inter(link.from.0)? || link.to.1 >= n_inter(link.to.0)? {
+                return None;
+            }
+        }
+        let link_from =
+            |ring: usize, inter: usize| links.iter().find(|link| link.from == (ring, inter));
+        for trip in &trips {
+            if trip.from >= n_inter(trip.ring)? {
+                return None;
+            }
+            let mut ring = trip.ring;
+            for (k, exit) in trip.exits.iter().enumerate() {
+                if *exit >= n_inter(ring)? {
+                    return None;
+                }
+                if k + 1 < trip.exits.len() {
+                    ring = link_from(ring, *exit)?.to.0;
+                }
+            }
+        }
+        let first_id = rings.iter().map(|ring| ring.next_id).max()?;
+        for (i, trip) in trips.iter().enumerate() {
+            rings[trip.ring].add_arrival(Arrival {
+                t: trip.t,
+                from: trip.from,
+                to: trip.exits[0],
+                vel: trip.vel,
+                class: trip.class.clone(),
+                id: Some(first_id + i),
+            });
+        }
+        let mut network = RoundaboutNetwork {
+            t: 0.0,
+            t_done: vec![None; trips.len()],
+            first_id,
+            legs: (0..trips.len()).map(|i| (first_id + i, 0)).collect(),
+            on_links: HashMap::new(),
+            link_queues: vec![VecDeque::new(); links.len()],
+            link_free: vec![0.0; links.len()],
+            seen: vec![(0, 0); rings.len()],
+            rings,
+            links,
+            trips,
+        };
+        // cars of the roundabouts' own demand come after the trips
+        let next_id = network.first_id + network.trips.len();
+        for ring in &mut network.rings {
+            ring.next_id = next_id;
+        }
+        Some(network)
+    }
+    /**
+        network in the file @path, in the format of its extension
+    */
+    pub fn from_file(path: &str) -> Option<RoundaboutNetwork> {
+        match read_json(path) {
+            Ok(jobj) => RoundaboutNetwork::new(&jobj),
+            Err(err) => {
+                println!("{err}");
+                None
+            }
+        }
+    }
+    /**
+        index of the link leaving intersection @inter of @ring, None if there is none
+    */
+    pub fn link_from(&self, ring: usize, inter: usize) -> Option<usize> {
+        self.links
+            .iter()
+            .position(|link| link.from == (ring, inter))
+    }
+    /**
+        number of cars on @link, the ones queuing at its end to enter included
+    */
+    pub fn n_on_link(&self, link: usize) -> usize {
+        self.on_links.values().filter(|on| **on == link).count()
+    }
+    /**
+        number of cars waiting at the start of @link for it to have space
+    */
+    pub fn n_link_queue(&self, link: usize) -> usize {
+        self.link_queues[link].len()
+    }
+    /**
+        trip of the car @id, None for the cars of a roundabout's own traffic
+    */
+    pub fn trip_of(&self, id: usize) -> Option<usize> {
+        (self.first_id..self.first_id + self.trips.len())
+            .contains(&id)
+            .then(|| id - self.first_id)
+    }
+    /**
+        put the car of @trip on @link at time @t, reaching its end after the travel time
+    */
+    fn enter_link(&mut self, trip: usize, link: usize, t: Real) {
+        let id = self.first_id + trip;
+        let (ring, inter) = self.links[link].to;
+        let arrival = Arrival {
+            t: t + self.links[link].travel_time,
+            from: inter,
+            to: self.trips[trip].exits[self.legs[&id]],
+            vel: self.trips[trip].vel,
+            class: self.trips[trip].class.clone(),
+            id: Some(id),
+        };
+        self.rings[ring].add_arrival(arrival);
+        self.on_links.insert(id, link);
+    }
+    /**
+        step the roundabouts furthest behind, then move the cars that left them onto
+        the links. Returns true once every trip and every car is done
+    */
+    pub fn update(&mut self) -> bool {
+        let t = self.t;
+        for ring in &mut self.rings {
+            if !is_busy(ring) {
+                // idle roundabouts keep up, a car may come at any time
+                ring.t = ring.t.max(t);
+            } else if ring.t <= t {
+                ring.update();
+            }
+        }
+        // cars that entered the next roundabout are off their link
+        for ring in &self.rings {
+            for car in &ring.cars {
+                let car = car.borrow();
+                if let Some(link) = self.on_links.remove(&car.id) {
+                    self.link_free[link] = self.link_free[link].max(car.t_enter);
+                }
+            }
+        }
+        for i in 0..self.rings.len() {
+            let (n_finished, n_removed) = self.seen[i];
+            let ring = &self.rings[i];
+            let left: Vec<(usize, usize, Real)> = ring.finished_cars[n_finished..]
+                .iter()
+                .map(|car| {
+                    let car = car.borrow();
+                    (car.id, car.arm, car.t_exit)
+                })
+                .collect();
+            let removed: Vec<usize> = ring.removed_cars[n_removed..]
+                .iter()
+                .map(|car| car.borrow().id)
+                .collect();
+            self.seen[i] = (ring.finished_cars.len(), ring.removed_cars.len());
+            for id in removed {
+                self.legs.remove(&id);
+            }
+            for (id, arm, t_exit) in left {
+                let (Some(trip), Some(leg)) = (self.trip_of(id), self.legs.get(&id).copied())
+                else {
+                    continue;
+                };
+                let link = self.link_from(i, arm);
+                match link {
+                    Some(link) if leg + 1 < self.trips[trip].exits.len() => {
+                        self.legs.insert(id, leg + 1);
+                        if self.link_queues[link].is_empty()
+                            && self.n_on_link(link) < self.links[link].capacity
+                        {
+                            self.enter_link(trip, link, t_exit);
+                        } else {
+                            self.link_queues[link].push_back((trip, t_exit));
+                        }
+                    }
+                    _ => {
+                        // a rerouted car may leave where no link goes on
+                        self.legs.remove(&id);
+                        self.t_done[trip] = Some(t_exit);
+                        println!("Trip {trip} is done at time {t_exit}");
+                    }
+                }
+            }
+        }
+        // cars wait off the roundabout for space on the link
+        for link in 0..self.links.len() {
+            while !self.link_queues[link].is_empty()
+                && self.n_on_link(link) < self.links[link].capacity
+            {
+                let (trip, t_exit) = self.link_queues[link].pop_front().unwrap();
+                self.enter_link(trip, link, t_exit.max(self.link_free[link]));
+            }
+        }
+        let busy_t = self
+            .rings
+            .iter()
+            .filter(|ring| is_busy(ring))
+            .map(|ring| ring.t)
+            .fold(Real::INFINITY, Real::min);
+        let all_finished =
+            busy_t == Real::INFINITY && self.link_queues.iter().all(VecDeque::is_empty);
+        self.t = if all_finished {
+            self.rings.iter().map(|ring| ring.t).fold(self.t, Real::max)
+        } else {
+            busy_t
+        };
+        if all_finished {
+            println!("===== network finished in: {} =====", self.t);
+        }
+        all_finished
+    }
+    /**
+        whether the cars of a roundabout block each other for good, see
+        RoundaboutSim::is_stalled
+    */
+    pub fn is_stalled(&self) -> bool {
+        self.rings.iter().any(RoundaboutSim::is_stalled)
+    }
+    /**
+        travel time of the trips done, from entering the network to leaving it
+    */
+    pub fn trip_times(&self) -> Vec<Real> {
+        self.trips
+            .iter()
+            .zip(&self.t_done)
+            .filter_map(|(trip, t_done)| t_done.map(|t_done| t_done - trip.t))
+            .collect()
+    }
+    /**
+        travel time statistics of each roundabout, its own cars and the trips' alike
+    */
+    pub fn ring_stats(&self) -> Vec<Statistics> {
+        self.rings.iter().map(RoundaboutSim::stats).collect()
+    }
+}
+
+/**
+    whether @ring has cars on it, arriving or waiting at a crossing
+*/
+fn is_busy(ring: &RoundaboutSim) -> bool {
+    !ring.cars.is_empty() || ring.n_waiting() > 0 || ring.n_exiting() > 0
+}
+
+impl RoundaboutSim {
+    /**
+        car arriving at an entrance at @arrival.t, which may be in the past
+    */
+    pub(crate) fn add_arrival(&mut self, arrival: Arrival) {
+        let idx = self.arrivals.partition_point(|other| other.t <= arrival.t);
+        self.arrivals.insert(idx, arrival);
+    }
+}
+
+impl fmt::Display for RoundaboutNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let times = self.trip_times();
+        write!(f, "trips: done: {} / {}", times.len(), self.trips.len())?;
+        if !times.is_empty() {
+            write!(
+                f,
+                ", mean travel time: {}, max travel time: {}",
+                times.iter().sum::<Real>() / times.len() as Real,
+                times.iter().fold(0.0, |max: Real, t| t.max(max))
+            )?;
+        }
+        writeln!(f)?;
+        for (i, stats) in self.ring_stats().iter().enumerate() {
+            write!(f, "ring {i}: {stats}")?;
+        }
+        Ok(())
+    }
+}
+
+/**
+    run the network in @filename until every trip is done, a roundabout is stalled or
+    @max_t, None if the file cannot be loaded
+*/
+pub fn network_run(filename: &str, max_t: Real) -> Option<RoundaboutNetwork> {
+    let mut network = RoundaboutNetwork::from_file(filename)?;
+    while (network.t < max_t || max_t < 0.0) && !network.update() && !network.is_stalled() {}
+    Some(network)
+}
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{Action, Real};
+use crate::{Car, RoundaboutSim, RoundaboutSimSetting};
+
+/**
+    What can be seen of a car from outside the simulation, a copy that stays the
+    same when the simulation goes on
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct CarState {
+    pub id: usize,
+    pub r: Real,     // distance from the center, on the circle as long as a shaped lane
+    pub theta: Real, // angle on the roundabout in [0, 2 PI), along the circulation
+    pub x: Real,
+    pub y: Real,
+    pub lane: usize, // the lane it is on or switching from
+    pub action: Action,
+    pub vel: Real,         // current velocity
+    pub desired_vel: Real, // velocity reached without cars in front
+    pub arm: usize,        // destination intersection
+    pub class: String,
+    pub t_enter: Real,        // time entering the roundabout
+    pub t_exit: Option<Real>, // time leaving the roundabout, None while on it
+}
+
+impl Car {
+    pub fn state(&self, setting: &RoundaboutSimSetting) -> CarState {
+        let pos = self.pos(setting);
+        CarState {
+            id: self.id,
+            r: self.r(setting),
+            theta: self.theta(),
+            x: pos.re,
+            y: pos.im,
+            lane: self.lane,
+            action: self.action,
+            vel: self.speed,
+            desired_vel: self.vel,
+            arm: self.arm,
+            class: setting.classes[self.class].name.clone(),
+            t_enter: self.t_enter,
+            t_exit: (!self.t_exit.is_nan()).then_some(self.t_exit),
+        }
+    }
+}
+
+impl RoundaboutSim {
+    /**
+        cars on the roundabout
+    */
+    pub fn active_cars(&self) -> impl Iterator<Item = CarState> + '_ {
+        self.cars
+            .iter()
+            .map(|car| car.borrow().state(&self.setting))
+    }
+    /**
+        cars that left the roundabout and any crossing after it, in the order they left
+    */
+    pub fn exited_cars(&self) -> impl Iterator<Item = CarState> + '_ {
+        self.finished_cars
+            .iter()
+            .map(|car| car.borrow().state(&self.setting))
+    }
+    /**
+        cars taken off the roundabout by remove_car or a scripted event, in the order
+        they were removed
+    */
+    pub fn removed_cars(&self) -> impl Iterator<Item = CarState> + '_ {
+        self.removed_cars
+            .iter()
+            .map(|car| car.borrow().state(&self.setting))
+    }
+    /**
+        cars on @lane in the order kept on it, by angle in (-PI, PI]; none for a lane
+        that does not exist
+    */
+    pub fn lane_cars(&self, lane: usize) -> impl Iterator<Item = CarState> + '_ {
+        self.lanes
+            .get(lane)
+            .into_iter()
+            .flatten()
+            .map(|car| car.borrow().state(&self.setting))
+    }
+    /**
+        car @id wherever it is: on the roundabout, waiting at a crossing after leaving
+        it, finished or removed
+    */
+    pub fn find_car(&self, id: usize) -> Option<CarState> {
+        self.cars
+            .iter()
+            .chain(self.exit_queues.iter().flatten())
+            .chain(&self.finished_cars)
+            .chain(&self.removed_cars)
+            .find(|car| car.borrow().id == id)
+            .map(|car| car.borrow().state(&self.setting))
+    }
+}
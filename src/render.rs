@@ -2,13 +2,13 @@
 use crate::*;
 use macroquad::prelude::*;
 use num_complex::Complex;
-use std::f32::consts::PI;
 
-pub async fn render_run(filename: &str, max_t: f32) -> Option<RoundaboutSim> {
-    let mut sim = RoundaboutSim::from_json(filename)?;
+pub async fn render_run(filename: &str, max_t: Real) -> Option<RoundaboutSim> {
+    let mut sim = RoundaboutSim::from_file(filename)?;
     let mut finished = false;
     while (sim.t < max_t || max_t < 0.0) && !finished {
-        finished |= sim.update();
+        // a stalled simulation would stay as it is
+        finished |= sim.update() || sim.is_stalled();
         render_update(&sim);
         next_frame().await
         // TODO: delay such that simulation time is proportional to wall clock time
@@ -16,6 +16,7 @@ pub async fn render_run(filename: &str, max_t: f32) -> Option<RoundaboutSim> {
     Some(sim)
 }
 
+#[allow(clippy::unnecessary_cast)] // macroquad draws in f32, Real may be f32 or f64
 fn render_update(sim: &RoundaboutSim) {
     let lane_colors = [
         Color::new(0.78, 0.62, 0.78, 1.0), // MAUVE
@@ -39,7 +40,8 @@ fn render_update(sim: &RoundaboutSim) {
             Action::Stop => RED,
         }
     };
-    let car_size = DIST_ALLOW * 5.0;
+    let class_colors = [WHITE, ORANGE, YELLOW, SKYBLUE, PINK, LIME, VIOLET, BEIGE];
+    let car_size = (DIST_ALLOW * 5.0) as f32;
 
     clear_background(LIGHTGRAY);
     let setting = &sim.setting;
@@ -47,31 +49,93 @@ fn render_update(sim: &RoundaboutSim) {
         screen_width() / 2.0
     } else {
         screen_height() / 2.0
-    };
+    } as Real;
     set_camera(&Camera2D {
         zoom: vec2(2.0 / screen_width(), 2.0 / screen_height()),
         ..Default::default()
     });
     // draw lanes
-    let scale = scale / setting.r_lanes[0]; // world -1.0 ~ 1.0
+    let scale = scale / setting.extent(); // world -1.0 ~ 1.0
+    // screen coordinates of @pos, y points down on the screen
+    let screen = |pos: Complex<Real>| ((pos.re * scale) as f32, (-pos.im * scale) as f32);
+    let lane_segments = 128;
     for (i, r) in setting.r_lanes.iter().enumerate() {
-        draw_circle(0.0, 0.0, r * scale, lane_colors[i % lane_colors.len()]);
+        let color = lane_colors[i % lane_colors.len()];
+        if setting.curves.is_empty() {
+            draw_circle(0.0, 0.0, (r * scale) as f32, color);
+            continue;
+        }
+        // fan of triangles from the center
+        for k in 0..lane_segments {
+            let theta = |k: usize| 2.0 * consts::PI * (k as Real) / (lane_segments as Real);
+            let a = screen(setting.world_pos(i, 0.0, theta(k)));
+            let b = screen(setting.world_pos(i, 0.0, theta(k + 1)));
+            draw_triangle(vec2(0.0, 0.0), vec2(a.0, a.1), vec2(b.0, b.1), color);
+        }
     }
     for i in 0..setting.n_inter {
-        let pos = Complex::from_polar(
-            setting.r_lanes[0] * scale,
-            (i as f32) * 2.0 * PI / (setting.n_inter as f32),
-        );
-        draw_line(0.0, 0.0, pos.re, pos.im, aux_line_thinkness, aux_line_color);
+        let pos = screen(setting.world_pos(0, 0.0, setting.inter_theta(i)));
+        draw_line(0.0, 0.0, pos.0, pos.1, aux_line_thinkness, aux_line_color);
+    }
+    // draw crossings, red when a pedestrian is on it
+    for crossing in &setting.crossings {
+        let theta = setting.inter_theta(crossing.inter);
+        let from = screen(setting.world_pos(0, -0.1 * setting.r_lanes[0], theta));
+        let to = screen(setting.world_pos(0, 0.0, theta));
+        let color = if sim.is_crossing_blocked(crossing.inter) {
+            RED
+        } else {
+            WHITE
+        };
+        draw_line(from.0, from.1, to.0, to.1, 3.0 * aux_line_thinkness, color);
     }
-    // draw cars
+    // draw entrance signals
+    for signal in &setting.signals {
+        let pos = screen(setting.world_pos(
+            0,
+            -0.15 * setting.r_lanes[0],
+            setting.inter_theta(signal.inter),
+        ));
+        let color = match sim.signal_state(signal.inter) {
+            Some(SignalState::Green) => GREEN,
+            _ => RED,
+        };
+        draw_circle(pos.0, pos.1, 2.0 * car_size * scale as f32, color);
+    }
+    // draw solid lines between lanes
+    let solid_line_segments = 32;
+    for line in &setting.solid_lines {
+        let offset = (setting.r_lanes[line.lane + 1] - setting.r_lanes[line.lane]) / 2.0;
+        let arc = (line.to - line.from).rem_euclid(2.0 * consts::PI);
+        for i in 0..solid_line_segments {
+            let a = screen(setting.world_pos(
+                line.lane,
+                offset,
+                line.from + arc * (i as Real) / (solid_line_segments as Real),
+            ));
+            let b = screen(setting.world_pos(
+                line.lane,
+                offset,
+                line.from + arc * ((i + 1) as Real) / (solid_line_segments as Real),
+            ));
+            draw_line(a.0, a.1, b.0, b.1, aux_line_thinkness, WHITE);
+        }
+    }
+    // draw cars, class outside and action inside
     for car in &sim.cars {
-        let pos = &car.borrow().pos;
+        let car = car.borrow();
+        let pos = screen(car.pos(setting));
+        draw_circle(
+            pos.0,
+            pos.1,
+            car_size * scale as f32,
+            class_colors[car.class % class_colors.len()],
+        );
         draw_circle(
-            pos.re * scale,
-            -pos.im * scale, // TODO: coordinate by trial, Study...
-            car_size * scale,
-            match_action_to_color(&car.borrow().action),
+            pos.0,
+            pos.1,
+            0.6 * car_size * scale as f32,
+            match_action_to_color(&car.action),
         );
     }
 }
@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::RoundaboutSim;
+use crate::common::Real;
+
+/**
+    When RoundaboutSim::run_until stops, checked after every step in the order given
+*/
+pub enum StopCondition {
+    Time(Real),      // the time reaches it
+    Steps(usize),    // the number of steps reaches it
+    AllFinished,     // every car finished, which always stops the simulation
+    Finished(usize), // that many cars finished
+    Deadlock(Real),  // cars are on the roundabout but none moved for that long
+    Predicate(Box<dyn FnMut(&RoundaboutSim) -> bool>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Time,
+    Steps,
+    AllFinished,
+    Finished,
+    Deadlock, // also when the cars block each other for good, see RoundaboutSim::is_stalled
+    Predicate,
+    Violation, // an invariant was broken, see RoundaboutSim::violations
+}
+
+/**
+    where the cars on the roundabout are, to tell whether any moved
+*/
+fn positions(sim: &RoundaboutSim) -> Vec<(usize, usize, Real, Real)> {
+    sim.active_cars()
+        .map(|car| (car.id, car.lane, car.theta, car.r))
+        .collect()
+}
+
+impl RoundaboutSim {
+    /**
+        update until one of @conditions holds, calling @observe after every step.
+        The simulation is left as it stopped, with the reason returned
+    */
+    pub fn run_until(
+        &mut self,
+        conditions: &mut [StopCondition],
+        mut observe: impl FnMut(&RoundaboutSim),
+    ) -> StopReason {
+        let track_deadlock = conditions
+            .iter()
+            .any(|condition| matches!(condition, StopCondition::Deadlock(_)));
+        let mut last_positions = vec![];
+        let mut still_since = self.t;
+        let mut finished = false;
+        loop {
+            if finished {
+                return StopReason::AllFinished;
+            }
+            if track_deadlock {
+                let now = positions(self);
+                if now.is_empty() || now != last_positions {
+                    still_since = self.t;
+                }
+                last_positions = now;
+            }
+            for condition in conditions.iter_mut() {
+                let reason = match condition {
+                    StopCondition::Time(t) if self.t >= *t => StopReason::Time,
+                    StopCondition::Steps(steps) if self.step >= *steps => StopReason::Steps,
+                    StopCondition::Finished(n) if self.finished_cars.len() >= *n => {
+                        StopReason::Finished
+                    }
+                    StopCondition::Deadlock(duration) if self.t - still_since >= *duration => {
+                        StopReason::Deadlock
+                    }
+                    StopCondition::Predicate(holds) => match holds(self) {
+                        true => StopReason::Predicate,
+                        false => continue,
+                    },
+                    _ => continue,
+                };
+                return reason;
+            }
+            let n_violations = self.violations().len();
+            finished = self.update();
+            observe(self);
+            if self.violations().len() > n_violations {
+                return StopReason::Violation;
+            }
+            if self.is_stalled() {
+                return StopReason::Deadlock;
+            }
+        }
+    }
+}
+
+/**
+    run the configuration in @filename until one of @conditions holds, None if the
+    file cannot be loaded
+*/
+pub fn run_file(
+    filename: &str,
+    conditions: &mut [StopCondition],
+) -> Option<(RoundaboutSim, StopReason)> {
+    let mut sim = RoundaboutSim::from_file(filename)?;
+    let reason = sim.run_until(conditions, |_| {});
+    Some((sim, reason))
+}
@@ -1,20 +1,84 @@
+use crate::common::consts::PI;
+use crate::common::{Real, in_arc, unwrap_theta};
+use crate::config::{CONFIG_VERSION, Config};
+use crate::crossing::Crossing;
+use crate::demand::Demand;
+use crate::event::Event;
+use crate::geometry::{CURVATURE_SPAN, Curve, Shape};
+use crate::incident::{Incident, IncidentKind};
+use crate::signal::Signal;
+use crate::vehicle::VehicleClass;
 use json::{JsonValue, object};
-use std::f32::consts::PI;
-#[derive(Debug)]
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SwitchPolicy {
     // Handles the collision arising from switch to another lane
     SwitchFirst,
     StraightFirst,
-    // Random(f32), // switch will succed with probability f32, but this will create an imprecise simulation
+    // Random(Real), // switch will succed with probability Real, but this will create an imprecise simulation
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Engine {
+    // advance by tick, shortened to the earliest collision
+    Tick,
+    // jump to the next interaction (catch-up, switch completion, arrival, driver decision)
+    Event,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Circulation {
+    // direction cars go around, every angle of the configuration is measured along it
+    CounterClockwise, // right-hand traffic, like in continental Europe
+    Clockwise,        // left-hand traffic, like in the UK
+}
+
+/**
+    Lane divider that forbids switching between @lane and @lane + 1
+    over the arc from @from to @to (counter-clockwise)
+*/
+#[derive(Debug, Clone)]
+pub struct SolidLine {
+    pub lane: usize,
+    pub from: Real,
+    pub to: Real,
+}
+
+/**
+    Highest velocity @vel on @lane, over the arc from @arc.0 to @arc.1
+    (counter-clockwise) or the whole lane if @arc is None
+*/
+#[derive(Debug, Clone)]
+pub struct SpeedLimit {
+    pub lane: usize,
+    pub vel: Real,
+    pub arc: Option<(Real, Real)>,
 }
 
 #[derive(Debug)]
 pub struct RoundaboutSimSetting {
-    pub n_inter: usize,    // intersection
-    pub r_lanes: Vec<f32>, // radius of each lane
-    pub tick: f32,         // simulation update interval
+    pub n_inter: usize,     // intersection
+    pub r_lanes: Vec<Real>, // radius of each lane, the effective radius of curves
+    pub curves: Vec<Curve>, // shape of each lane, circles of r_lanes if empty
+    pub tick: Real,         // simulation update interval
     pub switch_policy: SwitchPolicy,
+    pub circulation: Circulation,
     // TODO: may provide DriverFactory so that other does not need to know detail
+    pub exit_lanes: Vec<Vec<usize>>, // lanes allowed to exit at each intersection, [0] if not given
+    pub solid_lines: Vec<SolidLine>,
+    pub speed_limits: Vec<SpeedLimit>,
+    pub lateral_accel: Option<Real>, // caps the velocity at sqrt(lateral_accel * r), r the local radius
+    pub demand: Option<Demand>,      // cars generated during the simulation
+    pub stats_bin: Option<Real>,     // width of the time bins of the statistics
+    pub classes: Vec<VehicleClass>,  // built-in classes come first
+    pub crossings: Vec<Crossing>,
+    pub signals: Vec<Signal>, // entrance signals, at most one per intersection
+    pub events: Vec<Event>,   // scripted changes to cars, by time
+    pub incidents: Vec<Incident>,
+    pub engine: Engine,
+    pub check_invariants: bool, // verify invariants after every step, for debugging
 }
 
 impl Default for RoundaboutSimSetting {
@@ -22,22 +86,303 @@ impl Default for RoundaboutSimSetting {
         RoundaboutSimSetting {
             n_inter: 2,
             r_lanes: vec![1.0],
+            curves: vec![],
             tick: 0.1,
             switch_policy: SwitchPolicy::StraightFirst,
+            circulation: Circulation::CounterClockwise,
+            exit_lanes: vec![],
+            solid_lines: vec![],
+            speed_limits: vec![],
+            lateral_accel: None,
+            demand: None,
+            stats_bin: None,
+            classes: VehicleClass::builtins(),
+            crossings: vec![],
+            signals: vec![],
+            events: vec![],
+            incidents: vec![],
+            engine: Engine::Tick,
+            check_invariants: false,
         }
     }
 }
 
 impl RoundaboutSimSetting {
     pub fn to_json(&self) -> JsonValue {
-        object! {
+        let mut jobj = object! {
+            version: CONFIG_VERSION,
             n_inter: self.n_inter,
             tick: self.tick,
-            r_lanes: self.r_lanes.clone(),
-            switch_policy: format!("{:?}", SwitchPolicy::StraightFirst),
+            switch_policy: format!("{:?}", self.switch_policy),
+            engine: format!("{:?}", self.engine),
+        };
+        if self.curves.is_empty() {
+            jobj["r_lanes"] = self.r_lanes.clone().into();
+        } else {
+            // r_lanes follow from the shapes
+            jobj["shapes"] = self
+                .curves
+                .iter()
+                .map(|curve| curve.shape.to_json())
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if self.check_invariants {
+            jobj["check_invariants"] = true.into();
+        }
+        if self.circulation != Circulation::CounterClockwise {
+            jobj["circulation"] = format!("{:?}", self.circulation).into();
+        }
+        if !self.exit_lanes.is_empty() {
+            jobj["exit_lanes"] = self.exit_lanes.clone().into();
+        }
+        if !self.solid_lines.is_empty() {
+            jobj["solid_lines"] = self
+                .solid_lines
+                .iter()
+                .map(|line| {
+                    object! {
+                        lane: line.lane,
+                        from: line.from,
+                        to: line.to,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if !self.speed_limits.is_empty() {
+            jobj["speed_limits"] = self
+                .speed_limits
+                .iter()
+                .map(|limit| {
+                    let mut jlimit = object! {
+                        lane: limit.lane,
+                        vel: limit.vel,
+                    };
+                    if let Some((from, to)) = limit.arc {
+                        jlimit["from"] = from.into();
+                        jlimit["to"] = to.into();
+                    }
+                    jlimit
+                })
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if let Some(lateral_accel) = self.lateral_accel {
+            jobj["lateral_accel"] = lateral_accel.into();
+        }
+        if let Some(demand) = &self.demand {
+            jobj["demand"] = demand.to_json();
+        }
+        if let Some(stats_bin) = self.stats_bin {
+            jobj["stats_bin"] = stats_bin.into();
+        }
+        if self.classes != VehicleClass::builtins() {
+            let mut classes = JsonValue::new_object();
+            for class in &self.classes {
+                classes[class.name.as_str()] = class.to_json();
+            }
+            jobj["classes"] = classes;
+        }
+        if !self.crossings.is_empty() {
+            jobj["crossings"] = self
+                .crossings
+                .iter()
+                .map(Crossing::to_json)
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if !self.signals.is_empty() {
+            jobj["signals"] = self
+                .signals
+                .iter()
+                .map(Signal::to_json)
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if !self.events.is_empty() {
+            jobj["events"] = self
+                .events
+                .iter()
+                .map(Event::to_json)
+                .collect::<Vec<_>>()
+                .into();
+        }
+        if !self.incidents.is_empty() {
+            jobj["incidents"] = self
+                .incidents
+                .iter()
+                .map(Incident::to_json)
+                .collect::<Vec<_>>()
+                .into();
+        }
+        jobj
+    }
+    /**
+        angle of intersection @inter
+    */
+    pub fn inter_theta(&self, inter: usize) -> Real {
+        2.0 * PI / (self.n_inter as Real) * (inter as Real)
+    }
+    /**
+        position in the plane of the point at @theta along the circulation on @lane,
+        moved by @offset (> 0 outwards) towards the adjacent lane like a switching car
+    */
+    pub fn world_pos(&self, lane: usize, offset: Real, theta: Real) -> Complex<Real> {
+        let pos = if self.curves.is_empty() {
+            Complex::from_polar(self.r_lanes[lane] + offset, theta)
+        } else {
+            let pos = self.curves[lane].point(theta);
+            let target = if offset > 0.0 {
+                lane.checked_sub(1)
+            } else {
+                Some(lane + 1).filter(|target| *target < self.curves.len())
+            };
+            match target {
+                _ if offset == 0.0 => pos,
+                Some(target) => {
+                    let ratio = offset / (self.r_lanes[target] - self.r_lanes[lane]);
+                    pos + (self.curves[target].point(theta) - pos) * ratio.abs()
+                }
+                // beyond the outermost or innermost lane
+                None => pos * ((self.r_lanes[lane] + offset) / self.r_lanes[lane]),
+            }
+        };
+        match self.circulation {
+            Circulation::CounterClockwise => pos,
+            Circulation::Clockwise => pos.conj(),
         }
     }
-    pub fn gen_random(n_cars: usize, n_inter: usize, r_lanes: &[f32]) -> JsonValue {
+    /**
+        largest distance of the outermost lane from the center
+    */
+    pub fn extent(&self) -> Real {
+        self.curves
+            .first()
+            .map_or(self.r_lanes[0], |curve| curve.extent())
+    }
+    /**
+        index of the vehicle class named @name
+    */
+    pub fn class_index(&self, name: &str) -> Option<usize> {
+        self.classes.iter().position(|class| class.name == name)
+    }
+    /**
+        whether a car on @lane may leave the roundabout at intersection @inter now
+    */
+    pub fn can_exit(&self, inter: usize, lane: usize) -> bool {
+        if self.exit_closed(inter) {
+            return false;
+        }
+        match self.exit_lanes.get(inter) {
+            Some(lanes) => lanes.contains(&lane),
+            None => lane == 0,
+        }
+    }
+    /**
+        the exit lane of intersection @inter closest to @lane
+    */
+    pub fn nearest_exit_lane(&self, inter: usize, lane: usize) -> usize {
+        match self.exit_lanes.get(inter) {
+            Some(lanes) => lanes
+                .iter()
+                .copied()
+                .min_by_key(|exit| exit.abs_diff(lane))
+                .unwrap_or(0),
+            None => 0,
+        }
+    }
+    /**
+        highest velocity at @theta on @lane now, by the speed limits, the lateral
+        acceleration and slow zones, infinity if there is no limit
+    */
+    pub fn speed_limit(&self, lane: usize, theta: Real) -> Real {
+        let mut limit = self.zone_vel(lane, theta);
+        for speed_limit in &self.speed_limits {
+            let on_arc = speed_limit
+                .arc
+                .is_none_or(|(from, to)| in_arc(theta, from, to));
+            if speed_limit.lane == lane && on_arc {
+                limit = limit.min(speed_limit.vel);
+            }
+        }
+        if let Some(lateral_accel) = self.lateral_accel {
+            let r = match self.curves.get(lane) {
+                Some(curve) => curve.radius(theta),
+                None => self.r_lanes[lane],
+            };
+            limit = limit.min((lateral_accel * r).sqrt());
+        }
+        limit
+    }
+    /**
+        angle from @theta to the next point of @lane where the speed limit may change,
+        infinity if it is the same everywhere
+    */
+    pub fn limit_boundary_theta(&self, lane: usize, theta: Real) -> Real {
+        let arcs = self
+            .speed_limits
+            .iter()
+            .filter(|limit| limit.lane == lane)
+            .filter_map(|limit| limit.arc);
+        let zones = self
+            .incidents
+            .iter()
+            .filter_map(|incident| match incident.kind {
+                IncidentKind::SlowZone {
+                    lane: slow,
+                    from,
+                    to,
+                    ..
+                } if incident.active && slow == lane => Some((from, to)),
+                _ => None,
+            });
+        // the lateral limit follows the curvature of a shaped lane, sampled every span
+        let curved = self.lateral_accel.is_some()
+            && self
+                .curves
+                .get(lane)
+                .is_some_and(|curve| !matches!(curve.shape, Shape::Circle { .. }));
+        let sample = if curved {
+            CURVATURE_SPAN - theta.rem_euclid(CURVATURE_SPAN)
+        } else {
+            Real::INFINITY
+        };
+        arcs.chain(zones)
+            .flat_map(|(from, to)| [from, to])
+            .map(|boundary| unwrap_theta(boundary - theta))
+            .filter(|ahead| *ahead > 0.0)
+            .fold(sample, Real::min)
+    }
+    /**
+        whether switching from @lane by @diff_lane is allowed at @theta now
+    */
+    pub fn switch_allowed(&self, lane: usize, diff_lane: i32, theta: Real) -> bool {
+        let target = lane as i32 + diff_lane;
+        if target < 0 || target as usize >= self.r_lanes.len() {
+            return false;
+        }
+        if self.lane_blocked(target as usize, theta) {
+            return false;
+        }
+        // the divider crossed is indexed by the outer lane
+        let divider = lane.min(target as usize);
+        !self
+            .solid_lines
+            .iter()
+            .any(|line| line.lane == divider && in_arc(theta, line.from, line.to))
+    }
+    /**
+        the last angle before @theta at which a car on @lane can still switch by @diff_lane
+    */
+    pub fn last_switch_theta(&self, lane: usize, diff_lane: i32, theta: Real) -> Real {
+        let divider = lane.min((lane as i32 + diff_lane).max(0) as usize);
+        self.solid_lines
+            .iter()
+            .find(|line| line.lane == divider && in_arc(theta, line.from, line.to))
+            .map_or(theta, |line| line.from)
+    }
+    pub fn gen_random(n_cars: usize, n_inter: usize, r_lanes: &[Real]) -> JsonValue {
         assert!(n_cars > 0);
         assert!(n_inter > 0);
         assert!(!r_lanes.is_empty());
@@ -49,10 +394,10 @@ impl RoundaboutSimSetting {
         let mut cars_json = JsonValue::new_object();
         for id in 0..n_cars {
             let cjson = object! {
-                vel: rand::random::<f32>() + 0.2,
+                vel: rand::random::<Real>() + 0.2,
                 lane: rand::random_range(0..r_lanes.len()),
                 dst: rand::random_range(0..n_inter),
-                theta: rand::random::<f32>() * 2.0 * PI,
+                theta: rand::random::<Real>() * 2.0 * PI,
             };
             cars_json[id.to_string()] = cjson;
         }
@@ -73,7 +418,7 @@ impl RoundaboutSimSetting {
                 vel: 1.0,
                 lane: 0,
                 dst: (id + 1) % n_cars,
-                theta: 2.0 * PI / (n_cars as f32) * (id as f32),
+                theta: 2.0 * PI / (n_cars as Real) * (id as Real),
             };
             cars_json[id.to_string()] = cjson;
         }
@@ -85,37 +430,88 @@ impl RoundaboutSimSetting {
         jobj["init"] = cars_json;
         jobj
     }
+    /**
+        setting of the configuration @jobj of any version, which may leave out the cars
+    */
     pub fn new(jobj: &JsonValue) -> Option<RoundaboutSimSetting> {
-        let mut r_lanes = vec![];
-        for it in jobj["r_lanes"].members() {
-            r_lanes.push(it.as_f32()?);
+        let mut jobj = jobj.clone();
+        if !jobj.has_key("init") {
+            jobj["init"] = JsonValue::new_object();
         }
-        {
-            let mut r_lanes_reverse = r_lanes.clone();
-            r_lanes_reverse.reverse();
-            assert!(
-                !r_lanes.is_empty() && r_lanes_reverse.is_sorted(),
-                "lanes should be of len > 0 and sorted in decreasing order"
-            );
+        RoundaboutSimSetting::from_config(&Config::from_json(&jobj).ok()?)
+    }
+    /**
+        setting of @config, None if its lanes or polylines are not usable
+    */
+    pub fn from_config(config: &Config) -> Option<RoundaboutSimSetting> {
+        let mut curves = vec![];
+        for shape in &config.shapes {
+            curves.push(Curve::new(Shape::from_config(shape)?));
         }
-        let ret = RoundaboutSimSetting {
-            n_inter: jobj["n_inter"].as_usize()?,
-            r_lanes,
-            tick: jobj["tick"].as_f32()?,
-            switch_policy: if jobj.has_key("switch_policy") {
-                match jobj["switch_policy"].as_str()? {
-                    "SwitchFirst" => SwitchPolicy::SwitchFirst,
-                    _ => SwitchPolicy::StraightFirst,
-                }
-            } else {
-                RoundaboutSimSetting::default().switch_policy
-            },
-        };
-        if ret.r_lanes.is_empty() {
-            None
+        let r_lanes = if curves.is_empty() {
+            config.r_lanes.clone()
         } else {
-            Some(ret)
+            curves.iter().map(Curve::effective_radius).collect()
+        };
+        // lanes are given from the outermost
+        if r_lanes.is_empty() || !r_lanes.is_sorted_by(|outer, inner| outer > inner) {
+            return None;
+        }
+        let mut classes = VehicleClass::builtins();
+        for (name, class) in &config.classes {
+            match classes.iter().position(|known| known.name == *name) {
+                Some(i) => classes[i] = VehicleClass::from_config(name, class, &classes[i]),
+                None => classes.push(VehicleClass::from_config(name, class, &classes[0])),
+            }
+        }
+        let mut speed_limits = vec![];
+        for limit in &config.speed_limits {
+            let arc = match (limit.from, limit.to) {
+                (Some(from), Some(to)) => Some((from, to)),
+                (None, None) => None,
+                _ => return None,
+            };
+            speed_limits.push(SpeedLimit {
+                lane: limit.lane,
+                vel: limit.vel,
+                arc,
+            });
         }
+        let mut events: Vec<Event> = config.events.iter().map(Event::from_config).collect();
+        // stable, events at the same time apply in the order given
+        events.sort_by(|a, b| a.t.total_cmp(&b.t));
+        let default = RoundaboutSimSetting::default();
+        Some(RoundaboutSimSetting {
+            n_inter: config.n_inter,
+            r_lanes,
+            curves,
+            tick: config.tick,
+            switch_policy: config.switch_policy.unwrap_or(default.switch_policy),
+            circulation: config.circulation.unwrap_or(default.circulation),
+            exit_lanes: config.exit_lanes.clone(),
+            solid_lines: config
+                .solid_lines
+                .iter()
+                .map(|line| SolidLine {
+                    lane: line.lane,
+                    from: line.from,
+                    to: line.to,
+                })
+                .collect(),
+            speed_limits,
+            lateral_accel: config.lateral_accel,
+            demand: match &config.demand {
+                Some(demand) => Some(Demand::from_config(demand, config.n_inter)?),
+                None => None,
+            },
+            stats_bin: config.stats_bin,
+            classes,
+            crossings: config.crossings.iter().map(Crossing::from_config).collect(),
+            signals: config.signals.iter().map(Signal::from_config).collect(),
+            events,
+            incidents: config.incidents.iter().map(Incident::from_config).collect(),
+            engine: config.engine.unwrap_or(default.engine),
+            check_invariants: config.check_invariants.unwrap_or(default.check_invariants),
+        })
     }
 }
-
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::Real;
+use crate::config::SignalConfig;
+use json::{JsonValue, object};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignalState {
+    Green,
+    Red,
+}
+
+#[derive(Debug, Clone)]
+pub enum SignalControl {
+    // green for @green then red for @red, shifted by @offset
+    FixedTime {
+        green: Real,
+        red: Real,
+        offset: Real,
+    },
+    // turns green once @queue cars wait (or after @max_red), turns red once the queue
+    // is empty (or after @max_green), each state lasts at least its minimum
+    QueueActuated {
+        min_green: Real,
+        max_green: Real,
+        min_red: Real,
+        max_red: Real,
+        queue: usize,
+    },
+}
+
+/**
+    Signal gating the entrance of an intersection
+*/
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub inter: usize,
+    pub control: SignalControl,
+}
+
+impl Signal {
+    pub fn from_config(config: &SignalConfig) -> Signal {
+        match *config {
+            SignalConfig::FixedTime {
+                inter,
+                green,
+                red,
+                offset,
+            } => Signal {
+                inter,
+                control: SignalControl::FixedTime {
+                    green,
+                    red,
+                    offset: offset.unwrap_or(0.0),
+                },
+            },
+            SignalConfig::QueueActuated {
+                inter,
+                min_green,
+                max_green,
+                min_red,
+                max_red,
+                queue,
+            } => Signal {
+                inter,
+                control: SignalControl::QueueActuated {
+                    min_green,
+                    max_green,
+                    min_red,
+                    max_red,
+                    queue,
+                },
+            },
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        match self.control {
+            SignalControl::FixedTime { green, red, offset } => object! {
+                inter: self.inter,
+                type: "FixedTime",
+                green: green,
+                red: red,
+                offset: offset,
+            },
+            SignalControl::QueueActuated {
+                min_green,
+                max_green,
+                min_red,
+                max_red,
+                queue,
+            } => object! {
+                inter: self.inter,
+                type: "QueueActuated",
+                min_green: min_green,
+                max_green: max_green,
+                min_red: min_red,
+                max_red: max_red,
+                queue: queue,
+            },
+        }
+    }
+    /**
+        state at time @t given the current @state held since @since and
+        @queue cars waiting
+    */
+    pub fn next_state(
+        &self,
+        t: Real,
+        state: SignalState,
+        since: Real,
+        queue: usize,
+    ) -> SignalState {
+        let elapsed = t - since;
+        match self.control {
+            SignalControl::FixedTime { green, red, offset } => {
+                if (t + offset).rem_euclid(green + red) < green {
+                    SignalState::Green
+                } else {
+                    SignalState::Red
+                }
+            }
+            SignalControl::QueueActuated {
+                min_green,
+                max_green,
+                min_red,
+                max_red,
+                queue: threshold,
+            } => match state {
+                SignalState::Red
+                    if (elapsed >= min_red && queue >= threshold.max(1))
+                        || (elapsed >= max_red && queue > 0) =>
+                {
+                    SignalState::Green
+                }
+                SignalState::Green
+                    if (elapsed >= min_green && queue == 0) || elapsed >= max_green =>
+                {
+                    SignalState::Red
+                }
+                _ => state,
+            },
+        }
+    }
+    /**
+        earliest time after @t the state may change without any new car queueing
+    */
+    pub fn next_change(&self, t: Real, state: SignalState, since: Real) -> Real {
+        match self.control {
+            SignalControl::FixedTime { green, red, offset } => {
+                let phase = (t + offset).rem_euclid(green + red);
+                if phase < green {
+                    t + green - phase
+                } else {
+                    t + green + red - phase
+                }
+            }
+            SignalControl::QueueActuated {
+                min_green,
+                max_green,
+                min_red,
+                max_red,
+                ..
+            } => {
+                let (min, max) = match state {
+                    SignalState::Green => (min_green, max_green),
+                    SignalState::Red => (min_red, max_red),
+                };
+                [since + min, since + max]
+                    .into_iter()
+                    .filter(|change| *change > t)
+                    .fold(Real::INFINITY, Real::min)
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::{Action, real_from_json, real_to_json, reals_from_json, reals_to_json};
+use crate::demand::Arrival;
+use crate::drivers::DriverFactory;
+use crate::{Car, RoundaboutSim, RoundaboutSimSetting, SignalState, Violation, ViolationKind};
+use json::{JsonValue, object};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+const SNAPSHOT_VERSION: u32 = 2; // 1 kept the angles of the cars
+
+impl Car {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            id: self.id,
+            dist: real_to_json(self.dist),
+            offset: real_to_json(self.offset),
+            vel: real_to_json(self.vel),
+            speed: real_to_json(self.speed),
+            speed_cap: real_to_json(self.speed_cap),
+            lane: self.lane,
+            dst_dist: real_to_json(self.dst_dist),
+            arm: self.arm,
+            class: self.class,
+            action: format!("{:?}", self.action),
+            t_enter: real_to_json(self.t_enter),
+            t_exit: real_to_json(self.t_exit),
+        }
+    }
+    fn from_json(jobj: &JsonValue, setting: &RoundaboutSimSetting) -> Option<Car> {
+        let lane = jobj["lane"].as_usize()?;
+        let car = Car {
+            id: jobj["id"].as_usize()?,
+            dist: real_from_json(&jobj["dist"])?,
+            r_lane: *setting.r_lanes.get(lane)?,
+            offset: real_from_json(&jobj["offset"])?,
+            vel: real_from_json(&jobj["vel"])?,
+            speed: real_from_json(&jobj["speed"])?,
+            speed_cap: real_from_json(&jobj["speed_cap"])?,
+            lane,
+            dst_dist: real_from_json(&jobj["dst_dist"])?,
+            arm: jobj["arm"].as_usize()?,
+            class: jobj["class"].as_usize()?,
+            action: action_from_json(&jobj["action"])?,
+            t_enter: real_from_json(&jobj["t_enter"])?,
+            t_exit: real_from_json(&jobj["t_exit"])?,
+        };
+        let valid = car.arm < setting.n_inter && car.class < setting.classes.len();
+        valid.then_some(car)
+    }
+}
+
+/**
+    the Action written as its Debug format
+*/
+fn action_from_json(jobj: &JsonValue) -> Option<Action> {
+    match jobj.as_str()? {
+        "Straight" => Some(Action::Straight),
+        "Stop" => Some(Action::Stop),
+        action => {
+            let diff_lane = action.strip_prefix("Switch(")?.strip_suffix(')')?;
+            Some(Action::Switch(diff_lane.parse().ok()?))
+        }
+    }
+}
+
+impl Violation {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            step: self.step,
+            t: real_to_json(self.t),
+            kind: format!("{:?}", self.kind),
+            cars: self.cars.clone(),
+        }
+    }
+    fn from_json(jobj: &JsonValue) -> Option<Violation> {
+        let kind = match jobj["kind"].as_str()? {
+            "StraightWhileSwitching" => ViolationKind::StraightWhileSwitching,
+            "Overlap" => ViolationKind::Overlap,
+            "OffLane" => ViolationKind::OffLane,
+            "PassedThrough" => ViolationKind::PassedThrough,
+            "Backwards" => ViolationKind::Backwards,
+            _ => return None,
+        };
+        Some(Violation {
+            step: jobj["step"].as_usize()?,
+            t: real_from_json(&jobj["t"])?,
+            kind,
+            cars: jobj["cars"]
+                .members()
+                .map(JsonValue::as_usize)
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+fn cars_to_json<'a>(cars: impl IntoIterator<Item = &'a Rc<RefCell<Car>>>) -> JsonValue {
+    cars.into_iter()
+        .map(|car| car.borrow().to_json())
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn cars_from_json(jobj: &JsonValue, setting: &RoundaboutSimSetting) -> Option<Vec<Car>> {
+    jobj.members()
+        .map(|car| Car::from_json(car, setting))
+        .collect()
+}
+
+impl RoundaboutSim {
+    /**
+        complete state of the simulation, restore gives back a simulation that
+        continues bit-for-bit the same
+    */
+    pub fn snapshot(&self) -> JsonValue {
+        let mut drivers = JsonValue::new_object();
+        for car in &self.cars {
+            let id = car.borrow().id;
+            if let Some(driver) = self.drivers.get(&id) {
+                drivers[id.to_string()] = object! {
+                    name: driver.name(),
+                    state: driver.save_state(),
+                };
+            }
+        }
+        object! {
+            snapshot: SNAPSHOT_VERSION,
+            setting: self.setting.to_json(),
+            driver: self.driver.clone(),
+            t: real_to_json(self.t),
+            step: self.step,
+            next_id: self.next_id,
+            cars: cars_to_json(&self.cars),
+            lanes: self
+                .lanes
+                .iter()
+                .map(|same_lane| {
+                    same_lane
+                        .iter()
+                        .map(|car| car.borrow().id)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>(),
+            drivers: drivers,
+            finished_cars: cars_to_json(&self.finished_cars),
+            removed_cars: cars_to_json(&self.removed_cars),
+            exit_queues: self
+                .exit_queues
+                .iter()
+                .map(cars_to_json)
+                .collect::<Vec<_>>(),
+            arrivals: self
+                .arrivals
+                .iter()
+                .map(Arrival::to_json)
+                .collect::<Vec<_>>(),
+            queues: self
+                .queues
+                .iter()
+                .map(|queue| queue.iter().map(Arrival::to_json).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            blocked: self
+                .blocked
+                .iter()
+                .map(|blocked| {
+                    blocked
+                        .iter()
+                        .map(|(start, end)| reals_to_json(&[*start, *end]))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>(),
+            signal_states: self
+                .signal_states
+                .iter()
+                .map(|(state, since)| {
+                    object! {
+                        state: format!("{state:?}"),
+                        since: real_to_json(*since),
+                    }
+                })
+                .collect::<Vec<_>>(),
+            green_time: reals_to_json(&self.green_time),
+            events_done: self.events_done,
+            incidents_active: self
+                .setting
+                .incidents
+                .iter()
+                .map(|incident| incident.active)
+                .collect::<Vec<_>>(),
+            violations: self
+                .violations
+                .iter()
+                .map(Violation::to_json)
+                .collect::<Vec<_>>(),
+            stalled: self.stalled,
+        }
+    }
+    /**
+        simulation from a snapshot, None if @jobj is not a valid one
+    */
+    pub fn restore(jobj: &JsonValue) -> Option<RoundaboutSim> {
+        if jobj["snapshot"].as_u32()? != SNAPSHOT_VERSION {
+            return None;
+        }
+        let mut setting = RoundaboutSimSetting::new(&jobj["setting"])?;
+        let mut by_id = HashMap::new();
+        let mut cars = vec![];
+        let mut drivers = HashMap::new();
+        for car in cars_from_json(&jobj["cars"], &setting)? {
+            let jdriver = &jobj["drivers"][car.id.to_string()];
+            let mut driver = DriverFactory::make_boxed(jdriver["name"].as_str()?);
+            driver.load_state(&jdriver["state"])?;
+            drivers.insert(car.id, driver);
+            let car = Rc::new(RefCell::new(car));
+            by_id.insert(car.borrow().id, car.clone());
+            cars.push(car);
+        }
+        let mut lanes = vec![];
+        for (lane, ids) in jobj["lanes"].members().enumerate() {
+            let mut same_lane = VecDeque::new();
+            for id in ids.members() {
+                let car = by_id.get(&id.as_usize()?)?;
+                if car.borrow().lane != lane {
+                    return None;
+                }
+                same_lane.push_back(car.clone());
+            }
+            lanes.push(same_lane);
+        }
+        if lanes.len() != setting.r_lanes.len()
+            || lanes.iter().map(|same_lane| same_lane.len()).sum::<usize>() != cars.len()
+        {
+            return None;
+        }
+        let shared = |cars: Vec<Car>| -> Vec<_> {
+            cars.into_iter()
+                .map(|car| Rc::new(RefCell::new(car)))
+                .collect()
+        };
+        let finished_cars = shared(cars_from_json(&jobj["finished_cars"], &setting)?);
+        // snapshots from before scripted events have neither
+        let removed_cars = shared(cars_from_json(&jobj["removed_cars"], &setting)?);
+        let events_done = jobj["events_done"].as_usize().unwrap_or(0);
+        // and before incidents, they start at the next update
+        if jobj.has_key("incidents_active") {
+            if jobj["incidents_active"].len() != setting.incidents.len() {
+                return None;
+            }
+            for (incident, active) in setting
+                .incidents
+                .iter_mut()
+                .zip(jobj["incidents_active"].members())
+            {
+                incident.active = active.as_bool()?;
+            }
+        }
+        // and before the broken invariants and deadlocks were kept, none so far
+        let violations = jobj["violations"]
+            .members()
+            .map(Violation::from_json)
+            .collect::<Option<_>>()?;
+        let stalled = jobj["stalled"].as_bool().unwrap_or(false);
+        let mut exit_queues = vec![];
+        for queue in jobj["exit_queues"].members() {
+            exit_queues.push(shared(cars_from_json(queue, &setting)?).into());
+        }
+        let arrivals = jobj["arrivals"]
+            .members()
+            .map(Arrival::new)
+            .collect::<Option<_>>()?;
+        let mut queues = vec![];
+        for queue in jobj["queues"].members() {
+            queues.push(queue.members().map(Arrival::new).collect::<Option<_>>()?);
+        }
+        let mut blocked = vec![];
+        for intervals in jobj["blocked"].members() {
+            let mut pending = VecDeque::new();
+            for interval in intervals.members() {
+                let [start, end] = reals_from_json(interval)?[..] else {
+                    return None;
+                };
+                pending.push_back((start, end));
+            }
+            blocked.push(pending);
+        }
+        let mut signal_states = vec![];
+        for it in jobj["signal_states"].members() {
+            let state = match it["state"].as_str()? {
+                "Green" => SignalState::Green,
+                "Red" => SignalState::Red,
+                _ => return None,
+            };
+            signal_states.push((state, real_from_json(&it["since"])?));
+        }
+        let green_time = reals_from_json(&jobj["green_time"])?;
+        if exit_queues.len() != setting.n_inter
+            || queues.len() != setting.n_inter
+            || blocked.len() != setting.crossings.len()
+            || signal_states.len() != setting.signals.len()
+            || green_time.len() != setting.signals.len()
+            || events_done > setting.events.len()
+        {
+            return None;
+        }
+        Some(RoundaboutSim {
+            t: real_from_json(&jobj["t"])?,
+            step: jobj["step"].as_usize()?,
+            driver: jobj["driver"].clone(),
+            next_id: jobj["next_id"].as_usize()?,
+            setting,
+            finished_cars,
+            cars,
+            removed_cars,
+            lanes,
+            drivers,
+            arrivals,
+            queues,
+            blocked,
+            exit_queues,
+            signal_states,
+            green_time,
+            violations,
+            events_done,
+            stalled,
+        })
+    }
+}
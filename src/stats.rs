@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::RoundaboutSim;
+use crate::common::Real;
+use std::fmt;
+
+/**
+    Travel time of finished cars of one vehicle class
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ClassStatistics {
+    pub name: String,
+    pub n_finished: usize,
+    pub mean_travel_time: Real,
+    pub max_travel_time: Real,
+}
+
+/**
+    Pedestrians of one crossing until the current time
+*/
+#[derive(Debug, Clone, Default)]
+pub struct CrossingStatistics {
+    pub inter: usize,
+    pub n_pedestrians: usize,
+    pub blocked_time: Real,
+}
+
+/**
+    Timing of one entrance signal until the current time
+*/
+#[derive(Debug, Clone, Default)]
+pub struct SignalStatistics {
+    pub inter: usize,
+    pub green_time: Real,
+    pub red_time: Real,
+}
+
+/**
+    Cars of the time bin from @start to @end, the travel time is of the cars finishing
+    in it
+*/
+#[derive(Debug, Clone, Default)]
+pub struct BinStatistics {
+    pub start: Real,
+    pub end: Real,
+    pub n_arrived: usize, // generated by the demand, entering or not
+    pub n_entered: usize,
+    pub n_finished: usize,
+    pub mean_travel_time: Real,
+    pub max_travel_time: Real,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    pub n_finished: usize,
+    pub n_unfinished: usize, // on the roundabout, waiting to enter or waiting at a crossing
+    pub n_removed: usize,    // taken off the roundabout before finishing
+    pub classes: Vec<ClassStatistics>, // same order as setting.classes
+    pub crossings: Vec<CrossingStatistics>, // same order as setting.crossings
+    pub signals: Vec<SignalStatistics>, // same order as setting.signals
+    pub bins: Vec<BinStatistics>, // up to the current time, empty without setting.stats_bin
+}
+
+impl Statistics {
+    pub fn new(sim: &RoundaboutSim) -> Statistics {
+        let mut classes: Vec<ClassStatistics> = sim
+            .setting
+            .classes
+            .iter()
+            .map(|class| ClassStatistics {
+                name: class.name.clone(),
+                ..ClassStatistics::default()
+            })
+            .collect();
+        for car in &sim.finished_cars {
+            let car = car.borrow();
+            let travel_time = car.t_exit - car.t_enter;
+            let stats = &mut classes[car.class];
+            stats.n_finished += 1;
+            stats.mean_travel_time += travel_time;
+            stats.max_travel_time = stats.max_travel_time.max(travel_time);
+        }
+        for stats in &mut classes {
+            if stats.n_finished > 0 {
+                stats.mean_travel_time /= stats.n_finished as Real;
+            }
+        }
+        let crossings = sim
+            .setting
+            .crossings
+            .iter()
+            .map(|crossing| CrossingStatistics {
+                inter: crossing.inter,
+                n_pedestrians: crossing
+                    .gen_pedestrians()
+                    .iter()
+                    .filter(|t| **t <= sim.t)
+                    .count(),
+                blocked_time: crossing
+                    .gen_blocked()
+                    .iter()
+                    .map(|(start, end)| (end.min(sim.t) - start).max(0.0))
+                    .sum(),
+            })
+            .collect();
+        let signals = sim
+            .setting
+            .signals
+            .iter()
+            .zip(sim.green_time())
+            .map(|(signal, green_time)| SignalStatistics {
+                inter: signal.inter,
+                green_time: *green_time,
+                red_time: sim.t - green_time,
+            })
+            .collect();
+        Statistics {
+            bins: sim
+                .setting
+                .stats_bin
+                .map_or(vec![], |width| Statistics::bins(sim, width)),
+            n_finished: sim.finished_cars.len(),
+            n_unfinished: sim.cars.len() + sim.n_waiting() + sim.n_exiting(),
+            n_removed: sim.removed_cars().count(),
+            classes,
+            crossings,
+            signals,
+        }
+    }
+}
+
+impl Statistics {
+    /**
+        statistics of the time bins of @width from time 0 until now
+    */
+    pub fn bins(sim: &RoundaboutSim, width: Real) -> Vec<BinStatistics> {
+        let n_bins = (sim.t / width) as usize + 1;
+        let mut bins: Vec<BinStatistics> = (0..n_bins)
+            .map(|k| BinStatistics {
+                start: k as Real * width,
+                end: (k + 1) as Real * width,
+                ..BinStatistics::default()
+            })
+            .collect();
+        let bin = |t: Real| ((t / width) as usize).min(n_bins - 1);
+        if let Some(demand) = &sim.setting.demand {
+            for arrival in demand.gen_arrivals() {
+                if arrival.t <= sim.t {
+                    bins[bin(arrival.t)].n_arrived += 1;
+                }
+            }
+        }
+        let entered = sim
+            .cars
+            .iter()
+            .chain(&sim.finished_cars)
+            .chain(&sim.removed_cars)
+            .chain(sim.exit_queues.iter().flatten());
+        for car in entered {
+            bins[bin(car.borrow().t_enter)].n_entered += 1;
+        }
+        for car in &sim.finished_cars {
+            let car = car.borrow();
+            let travel_time = car.t_exit - car.t_enter;
+            let stats = &mut bins[bin(car.t_exit)];
+            stats.n_finished += 1;
+            stats.mean_travel_time += travel_time;
+            stats.max_travel_time = stats.max_travel_time.max(travel_time);
+        }
+        for stats in &mut bins {
+            if stats.n_finished > 0 {
+                stats.mean_travel_time /= stats.n_finished as Real;
+            }
+        }
+        bins
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "finished: {}, unfinished: {}",
+            self.n_finished, self.n_unfinished
+        )?;
+        if self.n_removed > 0 {
+            write!(f, ", removed: {}", self.n_removed)?;
+        }
+        writeln!(f)?;
+        for stats in self.classes.iter().filter(|stats| stats.n_finished > 0) {
+            writeln!(
+                f,
+                "{}: finished: {}, mean travel time: {}, max travel time: {}",
+                stats.name, stats.n_finished, stats.mean_travel_time, stats.max_travel_time
+            )?;
+        }
+        for stats in &self.crossings {
+            writeln!(
+                f,
+                "crossing at {}: pedestrians: {}, blocked time: {}",
+                stats.inter, stats.n_pedestrians, stats.blocked_time
+            )?;
+        }
+        for stats in &self.signals {
+            writeln!(
+                f,
+                "signal at {}: green time: {}, red time: {}",
+                stats.inter, stats.green_time, stats.red_time
+            )?;
+        }
+        for stats in &self.bins {
+            writeln!(
+                f,
+                "bin [{}, {}): arrived: {}, entered: {}, finished: {}, mean travel time: {}, max travel time: {}",
+                stats.start,
+                stats.end,
+                stats.n_arrived,
+                stats.n_entered,
+                stats.n_finished,
+                stats.mean_travel_time,
+                stats.max_travel_time
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::RoundaboutSim;
+use crate::common::{Action, Real, SWITCH_IN, SWITCH_OUT};
+use std::io::{self, Read, Write};
+
+const SAMPLE_ALLOW: Real = 1e-3; // steps ending this close before a sample time are sampled
+const MAGIC: &[u8; 4] = b"RTRJ";
+const COLUMNAR_VERSION: u32 = 1;
+
+/**
+    Column types of the columnar format, all little endian
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)] // only one of F32 and F64 is Real
+enum ColumnType {
+    F32 = 0,
+    F64 = 1,
+    U64 = 2,
+    U32 = 3,
+    U8 = 4,
+}
+
+// values are written at the precision of the simulation
+#[cfg(not(feature = "f64"))]
+const REAL: ColumnType = ColumnType::F32;
+#[cfg(feature = "f64")]
+const REAL: ColumnType = ColumnType::F64;
+
+const COLUMNS: [(&str, ColumnType); 9] = [
+    ("t", REAL),
+    ("id", ColumnType::U64),
+    ("x", REAL),
+    ("y", REAL),
+    ("r", REAL),
+    ("theta", REAL),
+    ("lane", ColumnType::U32),
+    ("action", ColumnType::U8),
+    ("vel", REAL),
+];
+
+/**
+    Cars on the roundabout sampled every @interval, one row per car and sample
+    kept column by column
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trajectory {
+    pub interval: Real, // time between samples, every step if not positive
+    next_t: Real,       // time of the next sample
+    pub t: Vec<Real>,
+    pub id: Vec<usize>,
+    pub x: Vec<Real>,
+    pub y: Vec<Real>,
+    pub r: Vec<Real>,     // distance from the center
+    pub theta: Vec<Real>, // angle on the roundabout in [0, 2 PI)
+    pub lane: Vec<usize>,
+    pub action: Vec<Action>,
+    pub vel: Vec<Real>, // current velocity
+}
+
+/**
+    code of @action in the columnar format
+*/
+fn action_code(action: Action) -> u8 {
+    match action {
+        Action::Straight => 0,
+        Action::Stop => 1,
+        Action::Switch(diff_lane) if diff_lane < 0 => 2,
+        Action::Switch(_) => 3,
+    }
+}
+
+fn action_from_code(code: u8) -> io::Result<Action> {
+    match code {
+        0 => Ok(Action::Straight),
+        1 => Ok(Action::Stop),
+        2 => Ok(SWITCH_OUT),
+        3 => Ok(SWITCH_IN),
+        _ => Err(invalid("unknown action code")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_column<T: Copy, const N: usize>(
+    writer: &mut impl Write,
+    column: &[T],
+    bytes: impl Fn(T) -> [u8; N],
+) -> io::Result<()> {
+    for x in column {
+        writer.write_all(&bytes(*x))?;
+    }
+    Ok(())
+}
+
+fn read_column<T, const N: usize>(
+    reader: &mut impl Read,
+    n_rows: usize,
+    value: impl Fn([u8; N]) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    (0..n_rows).map(|_| value(read_array(reader)?)).collect()
+}
+
+impl Trajectory {
+    pub fn new(interval: Real) -> Trajectory {
+        Trajectory {
+            interval,
+            ..Trajectory::default()
+        }
+    }
+    /**
+        number of rows
+    */
+    pub fn len(&self) -> usize {
+        self.t.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.t.is_empty()
+    }
+    /**
+        sample the cars of @sim if a sample is due, call it before the first update and
+        after every one
+    */
+    pub fn record(&mut self, sim: &RoundaboutSim) {
+        if sim.t + SAMPLE_ALLOW < self.next_t {
+            return;
+        }
+        for car in sim.active_cars() {
+            self.t.push(sim.t);
+            self.id.push(car.id);
+            self.x.push(car.x);
+            self.y.push(car.y);
+            self.r.push(car.r);
+            self.theta.push(car.theta);
+            self.lane.push(car.lane);
+            self.action.push(car.action);
+            self.vel.push(car.vel);
+        }
+        if self.interval > 0.0 {
+            // sample times stay multiples of the interval
+            while self.next_t <= sim.t + SAMPLE_ALLOW {
+                self.next_t += self.interval;
+            }
+        }
+    }
+    /**
+        rows as csv with a header line, actions as in snapshots
+    */
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let names: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+        writeln!(writer, "{}", names.join(","))?;
+        for i in 0..self.len() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{:?},{}",
+                self.t[i],
+                self.id[i],
+                self.x[i],
+                self.y[i],
+                self.r[i],
+                self.theta[i],
+                self.lane[i],
+                self.action[i],
+                self.vel[i]
+            )?;
+        }
+        Ok(())
+    }
+    /**
+        columnar binary format: "RTRJ", the version (u32), the number of rows (u64) and
+        of columns (u32), the name (u8 length and bytes) and ColumnType (u8) of each
+        column, then the values column after column. Reals are f32 or f64 like Real
+    */
+    pub fn write_columnar(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&COLUMNAR_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        writer.write_all(&(COLUMNS.len() as u32).to_le_bytes())?;
+        for (name, column_type) in COLUMNS {
+            writer.write_all(&[name.len() as u8])?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&[column_type as u8])?;
+        }
+        write_column(writer, &self.t, Real::to_le_bytes)?;
+        write_column(writer, &self.id, |id| (id as u64).to_le_bytes())?;
+        for column in [&self.x, &self.y, &self.r, &self.theta] {
+            write_column(writer, column, Real::to_le_bytes)?;
+        }
+        write_column(writer, &self.lane, |lane| (lane as u32).to_le_bytes())?;
+        write_column(writer, &self.action, |action| [action_code(action)])?;
+        write_column(writer, &self.vel, Real::to_le_bytes)
+    }
+    /**
+        trajectory written by write_columnar, its interval is not kept
+    */
+    pub fn read_columnar(reader: &mut impl Read) -> io::Result<Trajectory> {
+        if &read_array::<4>(reader)? != MAGIC {
+            return Err(invalid("not a trajectory"));
+        }
+        if u32::from_le_bytes(read_array(reader)?) != COLUMNAR_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let n_rows = u64::from_le_bytes(read_array(reader)?) as usize;
+        if u32::from_le_bytes(read_array(reader)?) as usize != COLUMNS.len() {
+            return Err(invalid("unexpected columns"));
+        }
+        for (name, column_type) in COLUMNS {
+            let [len] = read_array(reader)?;
+            let mut read_name = vec![0; len as usize];
+            reader.read_exact(&mut read_name)?;
+            if read_name != name.as_bytes() || read_array(reader)? != [column_type as u8] {
+                return Err(invalid("unexpected columns, or reals of another precision"));
+            }
+        }
+        let real = |bytes| Ok(Real::from_le_bytes(bytes));
+        Ok(Trajectory {
+            t: read_column(reader, n_rows, real)?,
+            id: read_column(reader, n_rows, |bytes| {
+                Ok(u64::from_le_bytes(bytes) as usize)
+            })?,
+            x: read_column(reader, n_rows, real)?,
+            y: read_column(reader, n_rows, real)?,
+            r: read_column(reader, n_rows, real)?,
+            theta: read_column(reader, n_rows, real)?,
+            lane: read_column(reader, n_rows, |bytes| {
+                Ok(u32::from_le_bytes(bytes) as usize)
+            })?,
+            action: read_column(reader, n_rows, |[code]| action_from_code(code))?,
+            vel: read_column(reader, n_rows, real)?,
+            ..Trajectory::default()
+        })
+    }
+}
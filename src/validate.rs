@@ -0,0 +1,905 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::consts::PI;
+use crate::common::{AsReal, Real, unwrap_theta};
+use crate::config::{
+    CarConfig, ClassConfig, Config, CrossingConfig, DemandConfig, EventConfig, IncidentConfig,
+    ProfileConfig, ProfilePointConfig, ShapeConfig, SignalConfig, SolidLineConfig,
+    SpeedLimitConfig, migrate,
+};
+use crate::drivers::DRIVER_NAMES;
+use crate::geometry::{Curve, Shape};
+use crate::vehicle::VehicleClass;
+use crate::{RoundaboutNetwork, RoundaboutSim};
+use json::JsonValue;
+use serde::de::value::MapDeserializer;
+use serde::de::{self, DeserializeOwned};
+use std::collections::HashSet;
+use std::fmt;
+
+/**
+    A problem of a configuration, @path locates the value like $.init.3.lane
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/**
+    Error of a probe deserialization, keeps the fields a struct expects when it
+    meets an unknown one
+*/
+#[derive(Debug)]
+struct SchemaProbe(&'static [&'static str]);
+
+impl fmt::Display for SchemaProbe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected one of {}", self.0.join(", "))
+    }
+}
+
+impl std::error::Error for SchemaProbe {}
+
+impl de::Error for SchemaProbe {
+    fn custom<T: fmt::Display>(_: T) -> SchemaProbe {
+        SchemaProbe(&[])
+    }
+    fn unknown_field(_: &str, expected: &'static [&'static str]) -> SchemaProbe {
+        SchemaProbe(expected)
+    }
+}
+
+/**
+    keys of the objects read as @T, of the variant @kind for the enums tagged by
+    "type", by feeding it a key no schema has
+*/
+fn schema_keys<T: DeserializeOwned>(kind: Option<&str>) -> Vec<&'static str> {
+    let mut entries = vec![];
+    if let Some(kind) = kind {
+        entries.push(("type".to_string(), kind.to_string()));
+    }
+    entries.push(("\0".to_string(), String::new()));
+    let probe = MapDeserializer::<_, SchemaProbe>::new(entries.into_iter());
+    let mut keys = match T::deserialize(probe) {
+        Err(SchemaProbe(expected)) => expected.to_vec(),
+        Ok(_) => vec![],
+    };
+    if kind.is_some() {
+        keys.push("type");
+    }
+    keys
+}
+
+struct Checker {
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    fn report(&mut self, path: &str, message: impl Into<String>) {
+        self.problems.push(Problem {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+    /**
+        whether @jobj is present, reported if @required
+    */
+    fn present(&mut self, jobj: &JsonValue, path: &str, required: bool) -> bool {
+        if jobj.is_null() {
+            if required {
+                self.report(path, "missing");
+            }
+            false
+        } else {
+            true
+        }
+    }
+    fn object(&mut self, jobj: &JsonValue, path: &str, keys: &[&str]) -> bool {
+        if !jobj.is_object() {
+            self.report(path, "expected an object");
+            return false;
+        }
+        for (key, _) in jobj.entries() {
+            if !keys.is_empty() && !keys.contains(&key) {
+                self.report(&format!("{path}.{key}"), "unknown key");
+            }
+        }
+        true
+    }
+    fn array(&mut self, jobj: &JsonValue, path: &str) -> bool {
+        if !jobj.is_array() {
+            self.report(path, "expected an array");
+        }
+        jobj.is_array()
+    }
+    /**
+        number at @path that must be at least @min, or above it if @strict
+    */
+    fn real(
+        &mut self,
+        jobj: &JsonValue,
+        path: &str,
+        required: bool,
+        min: Real,
+        strict: bool,
+    ) -> Option<Real> {
+        if !self.present(jobj, path, required) {
+            return None;
+        }
+        let Some(x) = jobj.as_real() else {
+            self.report(path, "expected a number");
+            return None;
+        };
+        if strict && x <= min {
+            self.report(path, format!("must be greater than {min}"));
+        } else if !strict && x < min {
+            self.report(path, format!("must be at least {min}"));
+        }
+        Some(x)
+    }
+    fn any_real(&mut self, jobj: &JsonValue, path: &str, required: bool) -> Option<Real> {
+        self.real(jobj, path, required, Real::NEG_INFINITY, false)
+    }
+    /**
+        index at @path that must be less than @bound, the number of @what
+    */
+    fn index(
+        &mut self,
+        jobj: &JsonValue,
+        path: &str,
+        required: bool,
+        bound: usize,
+        what: &str,
+    ) -> Option<usize> {
+        if !self.present(jobj, path, required) {
+            return None;
+        }
+        let Some(i) = jobj.as_usize() else {
+            self.report(path, "expected a non-negative integer");
+            return None;
+        };
+        if i >= bound {
+            self.report(
+                path,
+                format!("must be less than {bound}, the number of {what}"),
+            );
+        }
+        Some(i)
+    }
+    fn integer(&mut self, jobj: &JsonValue, path: &str) {
+        if self.present(jobj, path, false) && jobj.as_u64().is_none() {
+            self.report(path, "expected a non-negative integer");
+        }
+    }
+    fn boolean(&mut self, jobj: &JsonValue, path: &str) {
+        if self.present(jobj, path, false) && !jobj.is_boolean() {
+            self.report(path, "expected true or false");
+        }
+    }
+    fn one_of<'a>(
+        &mut self,
+        jobj: &'a JsonValue,
+        path: &str,
+        required: bool,
+        names: &[&str],
+    ) -> Option<&'a str> {
+        if !self.present(jobj, path, required) {
+            return None;
+        }
+        match jobj.as_str() {
+            Some(name) if names.contains(&name) => Some(name),
+            _ => {
+                self.report(path, format!("expected one of {}", names.join(", ")));
+                None
+            }
+        }
+    }
+    fn lanes(&mut self, jobj: &JsonValue, path: &str, n_lanes: usize) {
+        if self.array(jobj, path) {
+            for (i, lane) in jobj.members().enumerate() {
+                self.index(lane, &format!("{path}[{i}]"), true, n_lanes, "lanes");
+            }
+        }
+    }
+}
+
+/**
+    all problems of the configuration @jobj against the schema in the README and the
+    physical sanity rules, without running it
+*/
+pub fn validate(jobj: &JsonValue) -> Vec<Problem> {
+    let mut checker = Checker { problems: vec![] };
+    let c = &mut checker;
+    if jobj.has_key("snapshot") {
+        if RoundaboutSim::restore(jobj).is_none() {
+            c.report("$", "not a valid snapshot");
+        }
+        return checker.problems;
+    }
+    if !jobj.is_object() {
+        c.report("$", "expected an object");
+        return checker.problems;
+    }
+    if jobj.has_key("rings") {
+        validate_network(c, jobj);
+        return checker.problems;
+    }
+    let jobj = match migrate(jobj) {
+        Ok(jobj) => jobj,
+        Err(err) => {
+            c.report("$.version", err);
+            return checker.problems;
+        }
+    };
+    let jobj = &jobj;
+    c.object(jobj, "$", &schema_keys::<Config>(None));
+    if c.present(&jobj["comment"], "$.comment", false) && !jobj["comment"].is_string() {
+        c.report("$.comment", "expected a string");
+    }
+    let n_inter = match c.index(&jobj["n_inter"], "$.n_inter", true, usize::MAX, "") {
+        Some(0) => {
+            c.report("$.n_inter", "must be at least 1");
+            None
+        }
+        n_inter => n_inter,
+    };
+    // an unknown count does not make every index wrong
+    let n_inter_bound = n_inter.unwrap_or(usize::MAX);
+    let mut r_lanes = vec![];
+    let jshapes = &jobj["shapes"];
+    let lanes_key = if jshapes.is_null() {
+        "r_lanes"
+    } else {
+        "shapes"
+    };
+    if !jshapes.is_null() && jobj.has_key("r_lanes") {
+        c.report("$.shapes", "cannot be given with r_lanes");
+    } else if !jshapes.is_null() && c.array(jshapes, "$.shapes") {
+        for (i, shape) in jshapes.members().enumerate() {
+            let path = format!("$.shapes[{i}]");
+            if !shape.is_object() {
+                c.report(&path, "expected an object");
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            let kinds = ["Circle", "Ellipse", "Polyline"];
+            let kind = c.one_of(&shape["type"], &field("type"), true, &kinds);
+            let keys = schema_keys::<ShapeConfig>(kind);
+            if kind.is_some() {
+                c.object(shape, &path, &keys);
+            }
+            match kind {
+                Some("Circle") => {
+                    c.real(&shape["r"], &field("r"), true, 0.0, true);
+                }
+                Some("Ellipse") => {
+                    c.real(&shape["a"], &field("a"), true, 0.0, true);
+                    c.real(&shape["b"], &field("b"), true, 0.0, true);
+                }
+                Some(_) => {
+                    let jpoints = &shape["points"];
+                    if c.present(jpoints, &field("points"), true)
+                        && c.array(jpoints, &field("points"))
+                    {
+                        for (k, point) in jpoints.members().enumerate() {
+                            let valid = point.len() == 2 && point.members().all(|x| x.is_number());
+                            if !point.is_array() || !valid {
+                                c.report(&format!("{path}.points[{k}]"), "expected [x, y]");
+                            }
+                        }
+                        if jpoints.len() < 3 {
+                            c.report(&field("points"), "needs at least 3 points");
+                        }
+                    }
+                    c.boolean(&shape["smooth"], &field("smooth"));
+                }
+                None => {}
+            }
+            // distances on a lane are those on the circle of the same perimeter, the
+            // unknown keys reported above aside
+            let mut known = shape.clone();
+            for (key, _) in shape.entries().filter(|(key, _)| !keys.contains(key)) {
+                known.remove(key);
+            }
+            let config = serde_json::from_str(&known.dump()).ok();
+            if let Some(shape) = config.as_ref().and_then(Shape::from_config) {
+                let r = Curve::new(shape).effective_radius();
+                if r_lanes.last().is_some_and(|last| r >= *last) {
+                    c.report(&path, "must be shorter than the lane outside of it");
+                }
+                r_lanes.push(r);
+            }
+        }
+        if jshapes.is_empty() {
+            c.report("$.shapes", "needs at least one lane");
+        }
+    } else if jshapes.is_null()
+        && c.present(&jobj["r_lanes"], "$.r_lanes", true)
+        && c.array(&jobj["r_lanes"], "$.r_lanes")
+    {
+        for (i, r) in jobj["r_lanes"].members().enumerate() {
+            let path = format!("$.r_lanes[{i}]");
+            if let Some(r) = c.real(r, &path, true, 0.0, true) {
+                if r_lanes.last().is_some_and(|last| r >= *last) {
+                    c.report(&path, "must be less than the lane outside of it");
+                }
+                r_lanes.push(r);
+            }
+        }
+        if jobj["r_lanes"].is_empty() {
+            c.report("$.r_lanes", "needs at least one lane");
+        }
+    }
+    let n_lanes = if r_lanes.is_empty() {
+        usize::MAX
+    } else {
+        jobj[lanes_key].len()
+    };
+    c.real(&jobj["tick"], "$.tick", true, 0.0, true);
+    let switch_policies = ["StraightFirst", "SwitchFirst"];
+    c.one_of(
+        &jobj["switch_policy"],
+        "$.switch_policy",
+        false,
+        &switch_policies,
+    );
+    let circulations = ["CounterClockwise", "Clockwise"];
+    c.one_of(&jobj["circulation"], "$.circulation", false, &circulations);
+    c.one_of(&jobj["engine"], "$.engine", false, &["Tick", "Event"]);
+    c.boolean(&jobj["check_invariants"], "$.check_invariants");
+    c.one_of(&jobj["driver"], "$.driver", false, DRIVER_NAMES);
+    let jexit_lanes = &jobj["exit_lanes"];
+    if c.present(jexit_lanes, "$.exit_lanes", false) && c.array(jexit_lanes, "$.exit_lanes") {
+        if jexit_lanes.len() > n_inter_bound {
+            c.report("$.exit_lanes", "more entries than intersections");
+        }
+        for (i, lanes) in jexit_lanes.members().enumerate() {
+            c.lanes(lanes, &format!("$.exit_lanes[{i}]"), n_lanes);
+        }
+    }
+    let jsolid_lines = &jobj["solid_lines"];
+    if c.present(jsolid_lines, "$.solid_lines", false) && c.array(jsolid_lines, "$.solid_lines") {
+        for (i, line) in jsolid_lines.members().enumerate() {
+            let path = format!("$.solid_lines[{i}]");
+            if c.object(line, &path, &schema_keys::<SolidLineConfig>(None)) {
+                let n_dividers = n_lanes.saturating_sub(1);
+                c.index(
+                    &line["lane"],
+                    &format!("{path}.lane"),
+                    true,
+                    n_dividers,
+                    "dividers",
+                );
+                c.any_real(&line["from"], &format!("{path}.from"), true);
+                c.any_real(&line["to"], &format!("{path}.to"), true);
+            }
+        }
+    }
+    let jspeed_limits = &jobj["speed_limits"];
+    if c.present(jspeed_limits, "$.speed_limits", false) && c.array(jspeed_limits, "$.speed_limits")
+    {
+        for (i, limit) in jspeed_limits.members().enumerate() {
+            let path = format!("$.speed_limits[{i}]");
+            if !c.object(limit, &path, &schema_keys::<SpeedLimitConfig>(None)) {
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            c.index(&limit["lane"], &field("lane"), true, n_lanes, "lanes");
+            c.real(&limit["vel"], &field("vel"), true, 0.0, true);
+            // a segment needs both ends, the whole lane neither
+            let segment = limit.has_key("from") || limit.has_key("to");
+            c.any_real(&limit["from"], &field("from"), segment);
+            c.any_real(&limit["to"], &field("to"), segment);
+        }
+    }
+    c.real(&jobj["lateral_accel"], "$.lateral_accel", false, 0.0, true);
+    // classes come before everything referring to them
+    let mut class_lanes: Vec<(String, Vec<usize>)> = VehicleClass::builtins()
+        .into_iter()
+        .map(|class| (class.name, class.lanes))
+        .collect();
+    let mut class_lengths: Vec<Real> = VehicleClass::builtins()
+        .iter()
+        .map(|class| class.length)
+        .collect();
+    let jclasses = &jobj["classes"];
+    if c.present(jclasses, "$.classes", false) && c.object(jclasses, "$.classes", &[]) {
+        for (name, class) in jclasses.entries() {
+            let path = format!("$.classes.{name}");
+            if !c.object(class, &path, &schema_keys::<ClassConfig>(None)) {
+                continue;
+            }
+            c.real(&class["vel"], &format!("{path}.vel"), false, 0.0, true);
+            let length = c.real(
+                &class["length"],
+                &format!("{path}.length"),
+                false,
+                0.0,
+                false,
+            );
+            c.real(&class["accel"], &format!("{path}.accel"), false, 0.0, true);
+            let mut lanes = vec![];
+            if c.present(&class["lanes"], &format!("{path}.lanes"), false) {
+                c.lanes(&class["lanes"], &format!("{path}.lanes"), n_lanes);
+                lanes = class["lanes"]
+                    .members()
+                    .filter_map(JsonValue::as_usize)
+                    .collect();
+            }
+            c.one_of(
+                &class["driver"],
+                &format!("{path}.driver"),
+                false,
+                DRIVER_NAMES,
+            );
+            match class_lanes.iter().position(|(known, _)| known == name) {
+                Some(i) => {
+                    if class.has_key("lanes") {
+                        class_lanes[i].1 = lanes;
+                    }
+                    if let Some(length) = length {
+                        class_lengths[i] = length;
+                    }
+                }
+                None => {
+                    class_lanes.push((name.to_string(), lanes));
+                    class_lengths.push(length.unwrap_or(class_lengths[0]));
+                }
+            }
+        }
+    }
+    let class_names: Vec<&str> = class_lanes.iter().map(|(name, _)| name.as_str()).collect();
+    let jdemand = &jobj["demand"];
+    if c.present(jdemand, "$.demand", false)
+        && c.object(jdemand, "$.demand", &schema_keys::<DemandConfig>(None))
+    {
+        let jod = &jdemand["od"];
+        if c.present(jod, "$.demand.od", true) && c.array(jod, "$.demand.od") {
+            if n_inter.is_some_and(|n| jod.len() != n) {
+                c.report("$.demand.od", "needs a row for every intersection");
+            }
+            for (i, row) in jod.members().enumerate() {
+                let path = format!("$.demand.od[{i}]");
+                if !c.array(row, &path) {
+                    continue;
+                }
+                if n_inter.is_some_and(|n| row.len() != n) {
+                    c.report(&path, "needs a flow for every intersection");
+                }
+                for (j, flow) in row.members().enumerate() {
+                    c.real(flow, &format!("{path}[{j}]"), true, 0.0, false);
+                }
+            }
+        }
+        c.real(&jdemand["duration"], "$.demand.duration", true, 0.0, false);
+        c.real(&jdemand["scale"], "$.demand.scale", false, 0.0, false);
+        c.real(&jdemand["vel"], "$.demand.vel", false, 0.0, true);
+        let jmix = &jdemand["classes"];
+        if c.present(jmix, "$.demand.classes", false)
+            && c.object(jmix, "$.demand.classes", &class_names)
+        {
+            for (name, weight) in jmix.entries() {
+                c.real(
+                    weight,
+                    &format!("$.demand.classes.{name}"),
+                    true,
+                    0.0,
+                    false,
+                );
+            }
+        }
+        c.integer(&jdemand["seed"], "$.demand.seed");
+        let jprofile = &jdemand["profile"];
+        if c.present(jprofile, "$.demand.profile", false)
+            && c.object(
+                jprofile,
+                "$.demand.profile",
+                &schema_keys::<ProfileConfig>(None),
+            )
+        {
+            let interpolations = ["Step", "Linear"];
+            c.one_of(
+                &jprofile["interpolation"],
+                "$.demand.profile.interpolation",
+                false,
+                &interpolations,
+            );
+            let jpoints = &jprofile["points"];
+            if c.present(jpoints, "$.demand.profile.points", true)
+                && c.array(jpoints, "$.demand.profile.points")
+            {
+                let mut last_t = None;
+                for (k, point) in jpoints.members().enumerate() {
+                    let path = format!("$.demand.profile.points[{k}]");
+                    if !c.object(point, &path, &schema_keys::<ProfilePointConfig>(None)) {
+                        continue;
+                    }
+                    let t = c.real(&point["t"], &format!("{path}.t"), true, 0.0, false);
+                    if let (Some(t), Some(last_t)) = (t, last_t)
+                        && t <= last_t
+                    {
+                        c.report(&format!("{path}.t"), "must be after the previous point");
+                    }
+                    last_t = t.or(last_t);
+                    let jfactors = &point["factors"];
+                    let factors_path = format!("{path}.factors");
+                    if c.present(jfactors, &factors_path, true) && c.array(jfactors, &factors_path)
+                    {
+                        if n_inter.is_some_and(|n| jfactors.len() != n) {
+                            c.report(&factors_path, "needs a factor for every intersection");
+                        }
+                        for (i, factor) in jfactors.members().enumerate() {
+                            c.real(factor, &format!("{factors_path}[{i}]"), true, 0.0, false);
+                        }
+                    }
+                }
+                if jpoints.is_empty() {
+                    c.report("$.demand.profile.points", "needs at least one point");
+                }
+            }
+        }
+    }
+    c.real(&jobj["stats_bin"], "$.stats_bin", false, 0.0, true);
+    let jcrossings = &jobj["crossings"];
+    if c.present(jcrossings, "$.crossings", false) && c.array(jcrossings, "$.crossings") {
+        for (i, crossing) in jcrossings.members().enumerate() {
+            let path = format!("$.crossings[{i}]");
+            if !c.object(crossing, &path, &schema_keys::<CrossingConfig>(None)) {
+                continue;
+            }
+            let inter = format!("{path}.inter");
+            c.index(
+                &crossing["inter"],
+                &inter,
+                true,
+                n_inter_bound,
+                "intersections",
+            );
+            c.real(&crossing["rate"], &format!("{path}.rate"), true, 0.0, false);
+            c.real(
+                &crossing["cross_time"],
+                &format!("{path}.cross_time"),
+                true,
+                0.0,
+                false,
+            );
+            c.real(
+                &crossing["until"],
+                &format!("{path}.until"),
+                true,
+                0.0,
+                false,
+            );
+            c.boolean(&crossing["spillback"], &format!("{path}.spillback"));
+            c.integer(&crossing["seed"], &format!("{path}.seed"));
+        }
+    }
+    let jsignals = &jobj["signals"];
+    if c.present(jsignals, "$.signals", false) && c.array(jsignals, "$.signals") {
+        let mut inters = HashSet::new();
+        for (i, signal) in jsignals.members().enumerate() {
+            let path = format!("$.signals[{i}]");
+            if !signal.is_object() {
+                c.report(&path, "expected an object");
+                continue;
+            }
+            let inter = format!("{path}.inter");
+            if let Some(inter_idx) = c.index(
+                &signal["inter"],
+                &inter,
+                true,
+                n_inter_bound,
+                "intersections",
+            ) && !inters.insert(inter_idx)
+            {
+                c.report(&inter, "another signal is at this intersection");
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            let kinds = ["FixedTime", "QueueActuated"];
+            let kind = c.one_of(&signal["type"], &field("type"), true, &kinds);
+            if kind.is_some() {
+                c.object(signal, &path, &schema_keys::<SignalConfig>(kind));
+            }
+            match kind {
+                Some("FixedTime") => {
+                    c.real(&signal["green"], &field("green"), true, 0.0, true);
+                    c.real(&signal["red"], &field("red"), true, 0.0, false);
+                    c.any_real(&signal["offset"], &field("offset"), false);
+                }
+                Some(_) => {
+                    for (min, max) in [("min_green", "max_green"), ("min_red", "max_red")] {
+                        let min_value = c.real(&signal[min], &field(min), true, 0.0, false);
+                        let max_value = c.real(&signal[max], &field(max), true, 0.0, false);
+                        if let (Some(min_value), Some(max_value)) = (min_value, max_value)
+                            && max_value < min_value
+                        {
+                            c.report(&field(max), format!("must be at least {min}"));
+                        }
+                    }
+                    c.index(&signal["queue"], &field("queue"), true, usize::MAX, "");
+                }
+                None => {}
+            }
+        }
+    }
+    let jevents = &jobj["events"];
+    if c.present(jevents, "$.events", false) && c.array(jevents, "$.events") {
+        for (i, event) in jevents.members().enumerate() {
+            let path = format!("$.events[{i}]");
+            if !event.is_object() {
+                c.report(&path, "expected an object");
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            c.real(&event["t"], &field("t"), true, 0.0, false);
+            // cars generated by the demand can be targeted as well
+            c.index(&event["car"], &field("car"), true, usize::MAX, "");
+            let kinds = ["Remove", "Reroute", "SetVel"];
+            let kind = c.one_of(&event["type"], &field("type"), true, &kinds);
+            if kind.is_some() {
+                c.object(event, &path, &schema_keys::<EventConfig>(kind));
+            }
+            match kind {
+                Some("Remove") => {}
+                Some("Reroute") => {
+                    c.index(
+                        &event["dst"],
+                        &field("dst"),
+                        true,
+                        n_inter_bound,
+                        "intersections",
+                    );
+                }
+                Some(_) => {
+                    c.real(&event["vel"], &field("vel"), true, 0.0, true);
+                }
+                None => {}
+            }
+        }
+    }
+    let jincidents = &jobj["incidents"];
+    if c.present(jincidents, "$.incidents", false) && c.array(jincidents, "$.incidents") {
+        for (i, incident) in jincidents.members().enumerate() {
+            let path = format!("$.incidents[{i}]");
+            if !incident.is_object() {
+                c.report(&path, "expected an object");
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            let start = c.real(&incident["start"], &field("start"), false, 0.0, false);
+            let end = c.real(&incident["end"], &field("end"), false, 0.0, false);
+            if let Some(end) = end
+                && end < start.unwrap_or(0.0)
+            {
+                c.report(&field("end"), "must be at least start");
+            }
+            let kinds = ["LaneBlocked", "ExitClosed", "SlowZone"];
+            let kind = c.one_of(&incident["type"], &field("type"), true, &kinds);
+            if kind.is_some() {
+                c.object(incident, &path, &schema_keys::<IncidentConfig>(kind));
+            }
+            if kind == Some("ExitClosed") {
+                c.index(
+                    &incident["inter"],
+                    &field("inter"),
+                    true,
+                    n_inter_bound,
+                    "intersections",
+                );
+            } else if let Some(kind) = kind {
+                if kind == "SlowZone" {
+                    c.real(&incident["vel"], &field("vel"), true, 0.0, true);
+                }
+                c.index(&incident["lane"], &field("lane"), true, n_lanes, "lanes");
+                c.any_real(&incident["from"], &field("from"), true);
+                c.any_real(&incident["to"], &field("to"), true);
+            }
+        }
+    }
+    let jinit = &jobj["init"];
+    if c.present(jinit, "$.init", true) && c.object(jinit, "$.init", &[]) {
+        // (lane, theta, id, length) of the cars placed
+        let mut placed: Vec<(usize, Real, usize, Real)> = vec![];
+        for (key, car) in jinit.entries() {
+            let path = format!("$.init.{key}");
+            let id = key.parse::<usize>();
+            if id.is_err() {
+                c.report(&path, "car ids must be non-negative integers");
+            }
+            if !c.object(car, &path, &schema_keys::<CarConfig>(None)) {
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            let lane = c.index(&car["lane"], &field("lane"), true, n_lanes, "lanes");
+            let theta = c.any_real(&car["theta"], &field("theta"), true);
+            c.index(
+                &car["dst"],
+                &field("dst"),
+                true,
+                n_inter_bound,
+                "intersections",
+            );
+            c.real(&car["vel"], &field("vel"), false, 0.0, true);
+            c.one_of(&car["driver"], &field("driver"), false, DRIVER_NAMES);
+            let class = match c.one_of(&car["class"], &field("class"), false, &class_names) {
+                Some(name) => class_names.iter().position(|known| *known == name),
+                None if car.has_key("class") => None,
+                None => Some(0),
+            };
+            if let (Some(class), Some(lane)) = (class, lane)
+                && !class_lanes[class].1.is_empty()
+                && !class_lanes[class].1.contains(&lane)
+            {
+                c.report(&field("lane"), "not allowed for the vehicle class");
+            }
+            if let (Ok(id), Some(lane), Some(theta), Some(class)) = (id, lane, theta, class)
+                && lane < r_lanes.len()
+            {
+                placed.push((lane, unwrap_theta(theta), id, class_lengths[class]));
+            }
+        }
+        placed.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+        for (i, (lane, theta, id, _)) in placed.iter().enumerate() {
+            // the car in front on the same lane, the first one once around
+            let (next, lap) = match placed.get(i + 1) {
+                Some(next) if next.0 == *lane => (next, 0.0),
+                _ => match placed.iter().find(|other| other.0 == *lane) {
+                    Some(first) if first.2 != *id => (first, 2.0 * PI),
+                    _ => continue,
+                },
+            };
+            let (_, next_theta, next_id, next_length) = next;
+            let arc = (next_theta + lap - theta) * r_lanes[*lane];
+            if arc < *next_length || arc == 0.0 {
+                c.report(
+                    &format!("$.init.{id}"),
+                    format!("overlaps car {next_id} on lane {lane}"),
+                );
+            }
+        }
+    }
+    // the checks above are meant to cover everything the simulator rejects
+    if checker.problems.is_empty()
+        && Config::from_json(jobj)
+            .ok()
+            .and_then(|config| config.build_unchecked())
+            .is_none()
+    {
+        checker.report("$", "rejected by the simulator");
+    }
+    checker.problems
+}
+
+/**
+    problems of the network @jobj, those of each roundabout under $.rings[i]
+*/
+fn validate_network(c: &mut Checker, jobj: &JsonValue) {
+    c.object(jobj, "$", &["comment", "rings", "links", "trips"]);
+    let mut n_inters = vec![];
+    let jrings = &jobj["rings"];
+    if c.array(jrings, "$.rings") {
+        for (i, ring) in jrings.members().enumerate() {
+            for problem in validate(ring) {
+                let path = format!("$.rings[{i}]{}", &problem.path[1..]);
+                c.report(&path, problem.message);
+            }
+            n_inters.push(ring["n_inter"].as_usize().unwrap_or(usize::MAX));
+        }
+        if jrings.is_empty() {
+            c.report("$.rings", "needs at least one roundabout");
+        }
+    }
+    let n_rings = n_inters.len();
+    let n_inter = |ring: Option<usize>| {
+        ring.and_then(|ring| n_inters.get(ring).copied())
+            .unwrap_or(usize::MAX)
+    };
+    // (ring, intersection) at both ends of each valid link
+    let mut links: Vec<((usize, usize), usize)> = vec![];
+    let jlinks = &jobj["links"];
+    if c.present(jlinks, "$.links", false) && c.array(jlinks, "$.links") {
+        for (i, link) in jlinks.members().enumerate() {
+            let path = format!("$.links[{i}]");
+            if !c.object(link, &path, &["from", "to", "travel_time", "capacity"]) {
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            let mut ends = vec![];
+            for key in ["from", "to"] {
+                let end = &link[key];
+                if !end.is_array() || end.len() != 2 {
+                    c.report(&field(key), "expected [ring, intersection]");
+                    continue;
+                }
+                let ring = c.index(&end[0], &format!("{path}.{key}[0]"), true, n_rings, "rings");
+                let inter = c.index(
+                    &end[1],
+                    &format!("{path}.{key}[1]"),
+                    true,
+                    n_inter(ring),
+                    "intersections",
+                );
+                if let (Some(ring), Some(inter)) = (ring, inter) {
+                    ends.push((ring, inter));
+                }
+            }
+            c.real(
+                &link["travel_time"],
+                &field("travel_time"),
+                true,
+                0.0,
+                false,
+            );
+            if c.present(&link["capacity"], &field("capacity"), false)
+                && !matches!(link["capacity"].as_usize(), Some(capacity) if capacity > 0)
+            {
+                c.report(&field("capacity"), "expected a positive integer");
+            }
+            if let [from, to] = ends[..] {
+                if links.iter().any(|(other, _)| *other == from) {
+                    c.report(&field("from"), "another link leaves from this exit");
+                }
+                links.push((from, to.0));
+            }
+        }
+    }
+    let jtrips = &jobj["trips"];
+    if c.present(jtrips, "$.trips", true) && c.array(jtrips, "$.trips") {
+        for (i, trip) in jtrips.members().enumerate() {
+            let path = format!("$.trips[{i}]");
+            let keys = ["t", "ring", "from", "exits", "vel", "class"];
+            if !c.object(trip, &path, &keys) {
+                continue;
+            }
+            let field = |key: &str| format!("{path}.{key}");
+            c.real(&trip["t"], &field("t"), true, 0.0, false);
+            c.real(&trip["vel"], &field("vel"), false, 0.0, true);
+            if c.present(&trip["class"], &field("class"), false) && !trip["class"].is_string() {
+                c.report(&field("class"), "expected a string");
+            }
+            let ring = c.index(&trip["ring"], &field("ring"), true, n_rings, "rings");
+            c.index(
+                &trip["from"],
+                &field("from"),
+                true,
+                n_inter(ring),
+                "intersections",
+            );
+            let jexits = &trip["exits"];
+            if !c.present(jexits, &field("exits"), true) || !c.array(jexits, &field("exits")) {
+                continue;
+            }
+            if jexits.is_empty() {
+                c.report(&field("exits"), "needs at least one exit");
+            }
+            // follow the route, the roundabout after an unknown exit is unknown
+            let mut ring = ring;
+            for (k, exit) in jexits.members().enumerate() {
+                let path = format!("{path}.exits[{k}]");
+                let exit = c.index(exit, &path, true, n_inter(ring), "intersections");
+                if k + 1 == jexits.len() {
+                    break;
+                }
+                ring = match (ring, exit) {
+                    (Some(ring), Some(exit)) => {
+                        let next = links.iter().find(|(from, _)| *from == (ring, exit));
+                        if next.is_none() {
+                            c.report(&path, "no link leaves from this exit");
+                        }
+                        next.map(|(_, to)| *to)
+                    }
+                    _ => None,
+                };
+            }
+        }
+    }
+    if c.problems.is_empty() && RoundaboutNetwork::new(jobj).is_none() {
+        c.report("$", "rejected by the simulator");
+    }
+}
@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::common::Real;
+use crate::config::ClassConfig;
+use json::{JsonValue, object};
+
+/**
+    Parameters shared by every car of the same kind
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleClass {
+    pub name: String,
+    pub vel: Real,              // default velocity
+    pub length: Real,           // arc occupied behind the car position
+    pub accel: Real,            // Real::INFINITY means reaching vel instantly
+    pub lanes: Vec<usize>,      // lanes the class may use, empty means all
+    pub driver: Option<String>, // overrides the default driver
+}
+
+impl VehicleClass {
+    fn builtin(
+        name: &str,
+        vel: Real,
+        length: Real,
+        accel: Real,
+        lanes: Vec<usize>,
+    ) -> VehicleClass {
+        VehicleClass {
+            name: name.to_string(),
+            vel,
+            length,
+            accel,
+            lanes,
+            driver: None,
+        }
+    }
+    /**
+        car is the class of cars not specifying one, it keeps cars as points
+    */
+    pub fn builtins() -> Vec<VehicleClass> {
+        vec![
+            VehicleClass::builtin("car", 1.0, 0.0, Real::INFINITY, vec![]),
+            VehicleClass::builtin("truck", 0.6, 0.1, 0.5, vec![]),
+            VehicleClass::builtin("bus", 0.7, 0.1, 0.6, vec![0]),
+            VehicleClass::builtin("bicycle", 0.3, 0.02, 1.0, vec![0]),
+        ]
+    }
+    /**
+        @base provides the value of every key not given in @config
+    */
+    pub fn from_config(name: &str, config: &ClassConfig, base: &VehicleClass) -> VehicleClass {
+        VehicleClass {
+            name: name.to_string(),
+            vel: config.vel.unwrap_or(base.vel),
+            length: config.length.unwrap_or(base.length),
+            accel: config.accel.unwrap_or(base.accel),
+            lanes: config.lanes.clone().unwrap_or(base.lanes.clone()),
+            driver: config.driver.clone().or(base.driver.clone()),
+        }
+    }
+    pub fn to_json(&self) -> JsonValue {
+        let mut jobj = object! {
+            vel: self.vel,
+            length: self.length,
+            lanes: self.lanes.clone(),
+        };
+        // json has no infinity
+        if self.accel.is_finite() {
+            jobj["accel"] = self.accel.into();
+        }
+        if let Some(driver) = &self.driver {
+            jobj["driver"] = driver.as_str().into();
+        }
+        jobj
+    }
+    pub fn allows_lane(&self, lane: usize) -> bool {
+        self.lanes.is_empty() || self.lanes.contains(&lane)
+    }
+}
@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use json::{JsonValue, object};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use roundabout_sim::consts::PI;
+use roundabout_sim::*;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::{env, fs};
+
+/**
+    Random small scenarios run with every switch policy and driver, failing ones are
+    shrunk and written to target/property/ so they can be replayed like the test_jsons
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const N_CASES: usize = 40; // override with PROPERTY_CASES
+    const MAX_T: Real = 300.0; // every scenario is expected to finish well before
+    const DIST_ALLOW: Real = 1e-2; // finishing is detected this close to the exit
+
+    /**
+        json number as Real
+    */
+    fn real(jobj: &JsonValue) -> Real {
+        jobj.dump().parse().unwrap()
+    }
+
+    /**
+        scenario number @seed: a few cars on up to 3 lanes, never overlapping
+    */
+    fn gen_scenario(seed: u64) -> JsonValue {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n_inter = rng.random_range(2..=6);
+        let n_lanes = rng.random_range(1..=3);
+        let r0: Real = rng.random_range(2.0..5.0);
+        let r_lanes: Vec<Real> = (0..n_lanes).map(|i| r0 - 0.5 * i as Real).collect();
+        // distinct slots keep the cars apart
+        let n_slots = 24;
+        let mut slots: Vec<usize> = (0..n_slots * n_lanes).collect();
+        let mut init = JsonValue::new_object();
+        for id in 0..rng.random_range(1..=6) {
+            let slot = slots.swap_remove(rng.random_range(0..slots.len()));
+            init[id.to_string()] = object! {
+                lane: slot / n_slots,
+                theta: 2.0 * PI * (slot % n_slots) as Real / n_slots as Real,
+                dst: rng.random_range(0..n_inter),
+                vel: rng.random_range(0.3..1.3),
+            };
+        }
+        object! {
+            comment: format!("property scenario {seed}"),
+            n_inter: n_inter,
+            r_lanes: r_lanes,
+            tick: 0.1,
+            check_invariants: true,
+            init: init,
+        }
+    }
+
+    /**
+        run @scenario to the end, the error describes the first broken property
+    */
+    fn check(scenario: &JsonValue) -> Result<(), String> {
+        let run = |scenario: &JsonValue| -> Result<JsonValue, String> {
+            let setting = RoundaboutSimSetting::new(scenario).ok_or("bad setting")?;
+            let mut sim = RoundaboutSim::new(setting, scenario).ok_or("bad init")?;
+            let finished = catch_unwind(AssertUnwindSafe(|| {
+                while sim.t < MAX_T {
+                    if sim.update() {
+                        return true;
+                    }
+                }
+                false
+            }))
+            .map_err(|_| "panicked".to_string())?;
+            if let Some(violation) = sim.violations().first() {
+                return Err(format!("invariant violated at {violation}"));
+            }
+            if !finished {
+                return Err(format!("not finished by {MAX_T}"));
+            }
+            Ok(sim.snapshot())
+        };
+        let end = run(scenario)?;
+        // a car cannot beat driving the innermost lane at its own velocity
+        let r_lanes: Vec<Real> = scenario["r_lanes"].members().map(real).collect();
+        let n_inter = scenario["n_inter"].as_usize().unwrap();
+        for car in end["finished_cars"].members() {
+            let id = car["id"].as_usize().unwrap();
+            let init = &scenario["init"][id.to_string()];
+            let theta = real(&init["theta"]);
+            let dst = 2.0 * PI * real(&init["dst"]) / n_inter as Real;
+            let r_lane = r_lanes[init["lane"].as_usize().unwrap()];
+            let free_flow = (r_lanes[r_lanes.len() - 1] * (dst - theta).rem_euclid(2.0 * PI)
+                + (r_lane - r_lanes[0]).abs()
+                - DIST_ALLOW)
+                / real(&init["vel"]);
+            let t_exit = real(&car["t_exit"]);
+            if t_exit < free_flow {
+                return Err(format!("car {id} finished at {t_exit} before {free_flow}"));
+            }
+        }
+        // ids are labels only
+        let n_cars = scenario["init"].len();
+        let mut relabelled = scenario.clone();
+        relabelled["init"] = JsonValue::new_object();
+        for (key, car) in scenario["init"].entries() {
+            let id: usize = key.parse().unwrap();
+            relabelled["init"][(n_cars - 1 - id).to_string()] = car.clone();
+        }
+        let relabelled_end = run(&relabelled)?;
+        for car in end["finished_cars"].members() {
+            let id = car["id"].as_usize().unwrap();
+            let same = relabelled_end["finished_cars"]
+                .members()
+                .find(|other| other["id"].as_usize() == Some(n_cars - 1 - id))
+                .map(|other| other["t_exit"].clone());
+            if same != Some(car["t_exit"].clone()) {
+                return Err(format!("car {id} finishes differently once relabelled"));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+        smaller variants of @scenario: one car or the innermost lane less
+    */
+    fn shrink_candidates(scenario: &JsonValue) -> Vec<JsonValue> {
+        let mut candidates = vec![];
+        let cars: Vec<(String, JsonValue)> = scenario["init"]
+            .entries()
+            .map(|(key, car)| (key.to_string(), car.clone()))
+            .collect();
+        for skip in 0..cars.len() {
+            let mut smaller = scenario.clone();
+            smaller["init"] = JsonValue::new_object();
+            // keep the ids consecutive for relabelling
+            for (id, (_, car)) in cars.iter().enumerate().filter(|(i, _)| *i != skip) {
+                let id = if id > skip { id - 1 } else { id };
+                smaller["init"][id.to_string()] = car.clone();
+            }
+            candidates.push(smaller);
+        }
+        let n_lanes = scenario["r_lanes"].len();
+        if n_lanes > 1 && cars.iter().all(|(_, car)| car["lane"] != n_lanes - 1) {
+            let mut smaller = scenario.clone();
+            let _ = smaller["r_lanes"].pop();
+            candidates.push(smaller);
+        }
+        candidates
+    }
+
+    /**
+        greedily shrink the failing @scenario while it keeps failing
+    */
+    fn shrink(mut scenario: JsonValue, mut error: String) -> (JsonValue, String) {
+        'shrinking: loop {
+            for candidate in shrink_candidates(&scenario) {
+                if let Err(candidate_error) = check(&candidate) {
+                    scenario = candidate;
+                    error = candidate_error;
+                    continue 'shrinking;
+                }
+            }
+            return (scenario, error);
+        }
+    }
+
+    /**
+        scenario with @driver and @policy
+    */
+    fn with(scenario: &JsonValue, driver: &str, policy: &str) -> JsonValue {
+        let mut scenario = scenario.clone();
+        scenario["driver"] = driver.into();
+        scenario["switch_policy"] = policy.into();
+        scenario
+    }
+
+    #[test]
+    fn property_random_scenarios() {
+        let n_cases = env::var("PROPERTY_CASES")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(N_CASES);
+        let mut failures = vec![];
+        for seed in 0..n_cases as u64 {
+            for driver in ["ShortestDist", "ShortestTime"] {
+                for policy in ["StraightFirst", "SwitchFirst"] {
+                    let scenario = with(&gen_scenario(seed), driver, policy);
+                    let Err(error) = check(&scenario) else {
+                        continue;
+                    };
+                    let (mut minimal, error) = shrink(scenario, error);
+                    minimal["comment"] = format!("{}: {error}", minimal["comment"]).into();
+                    let dir = format!("{}/target/property", env!("CARGO_MANIFEST_DIR"));
+                    let path = format!("{dir}/{seed}_{driver}_{policy}.json");
+                    fs::create_dir_all(&dir).unwrap();
+                    fs::write(&path, json::stringify_pretty(minimal, 4)).unwrap();
+                    failures.push(format!("{path}: {error}"));
+                }
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "failing scenarios:\n{}",
+            failures.join("\n")
+        );
+    }
+}
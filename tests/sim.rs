@@ -1,14 +1,14 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 use approx::{assert_abs_diff_eq, assert_relative_eq};
+use roundabout_sim::consts::PI;
 use roundabout_sim::*;
-use std::f32::consts::PI;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    const RELATIVE: f32 = 1e-1;
+    const RELATIVE: Real = 1e-1;
 
-    fn check_completion_order(filename: &str, max_t: f32, order: &[usize]) {
+    fn check_completion_order(filename: &str, max_t: Real, order: &[usize]) {
         let sim = sim_run(filename, max_t).unwrap();
         assert_eq!(
             sim.finished_cars.len(),
@@ -114,6 +114,1163 @@ mod tests {
         check_completion_order("test_jsons/side_fast_slow_inward.json", 60.0, &[1, 0]);
     }
 
+    #[test]
+    /**
+        Verify exits served by inner lanes and switch prohibition by solid lines
+    */
+    fn sim_lane_discipline() {
+        // exit directly from the inner lane
+        assert_relative_eq!(
+            sim_run("test_jsons/turbo_inner_exit.json", 10.0).unwrap().t,
+            0.5 * PI,
+            max_relative = RELATIVE
+        );
+        // can't take the shortcut through the inner lane
+        assert_relative_eq!(
+            sim_run("test_jsons/solid_line_no_switch.json", 10.0)
+                .unwrap()
+                .t,
+            PI,
+            max_relative = RELATIVE
+        );
+        // switch out before the solid line covering the exit
+        assert_relative_eq!(
+            sim_run("test_jsons/solid_line_early_exit.json", 10.0)
+                .unwrap()
+                .t,
+            0.5 * 2.5 + 0.5 + (PI - 2.5),
+            max_relative = RELATIVE
+        );
+    }
+
+    #[test]
+    /**
+        Every car generated from the OD matrix enters and finishes
+    */
+    fn sim_od_demand() {
+        let sim = sim_run("test_jsons/od_demand.json", 100.0).unwrap();
+        let n_arrivals = sim.setting.demand.as_ref().unwrap().gen_arrivals().len();
+        assert!(n_arrivals > 0);
+        assert_eq!(sim.finished_cars.len(), n_arrivals);
+        // scaled demand generates proportionally more cars
+        let demand = Demand {
+            scale: 10.0,
+            ..sim.setting.demand.clone().unwrap()
+        };
+        let n_scaled = demand.gen_arrivals().len();
+        assert!(n_scaled > 5 * n_arrivals && n_scaled < 20 * n_arrivals);
+        // an od matrix that does not match the intersections is not built
+        let mut config = Config::from_file("test_jsons/od_demand.json").unwrap();
+        config.n_inter = 3;
+        assert!(config.build().is_none());
+        assert!(RoundaboutSimSetting::from_config(&config).is_none());
+    }
+
+    #[test]
+    /**
+        Flows follow the profile over the day and the statistics are split in time bins
+    */
+    fn sim_demand_profile() {
+        let path = "test_jsons/demand_profile.json";
+        let sim = sim_run(path, 200.0).unwrap();
+        let demand = sim.setting.demand.clone().unwrap();
+        let mut profile = demand.profile.clone().unwrap();
+        assert_eq!(profile.factor(0, 10.0), 2.0);
+        assert_eq!(profile.factor(0, 30.0), 0.5);
+        assert_eq!(profile.factor(2, 100.0), 2.0);
+        profile.interpolation = Interpolation::Linear;
+        assert_relative_eq!(profile.factor(0, 10.0), 1.25);
+        assert_eq!(profile.factor(2, 100.0), 2.0);
+        // the morning peak enters at 0 and the evening one at 2
+        let arrivals = demand.gen_arrivals();
+        let count = |from: usize, start: Real| {
+            arrivals
+                .iter()
+                .filter(|arrival| arrival.from == from)
+                .filter(|arrival| start <= arrival.t && arrival.t < start + 20.0)
+                .count()
+        };
+        assert!(count(0, 0.0) > 2 * count(0, 40.0));
+        assert!(count(2, 40.0) > 2 * count(2, 0.0));
+        let bins = sim.stats().bins;
+        assert_eq!(bins[1].start, 20.0);
+        assert_eq!(bins[1].end, 40.0);
+        let n_finished: usize = bins.iter().map(|bin| bin.n_finished).sum();
+        assert_eq!(n_finished, sim.finished_cars.len());
+        let n_arrived: usize = bins.iter().map(|bin| bin.n_arrived).sum();
+        assert_eq!(n_arrived, arrivals.len());
+        assert!(bins[1].n_arrived < bins[0].n_arrived && bins[1].n_arrived < bins[2].n_arrived);
+        let mut jobj = config::read_json(path).unwrap();
+        jobj["demand"]["profile"]["points"][2]["t"] = 10.0.into();
+        jobj["demand"]["profile"]["points"][0]["factors"] = json::array![1.0];
+        jobj["stats_bin"] = 0.0.into();
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.demand.profile.points[0].factors",
+                "$.demand.profile.points[2].t",
+                "$.stats_bin"
+            ]
+        );
+    }
+
+    #[test]
+    /**
+        Vehicle classes: speed, length and allowed lanes, reported separately
+    */
+    fn sim_vehicle_classes() {
+        let sim = sim_run("test_jsons/classes_follow.json", 20.0).unwrap();
+        let stats = sim.stats();
+        let car = &stats.classes[sim.setting.class_index("car").unwrap()];
+        let truck = &stats.classes[sim.setting.class_index("truck").unwrap()];
+        assert_eq!(car.n_finished, 1);
+        assert_eq!(truck.n_finished, 1);
+        assert_relative_eq!(
+            truck.mean_travel_time,
+            (PI - 0.5) / 0.6,
+            max_relative = RELATIVE
+        );
+        // stuck behind the truck
+        assert!(car.mean_travel_time > truck.mean_travel_time);
+        // the bus never takes the inner lane
+        assert_relative_eq!(
+            sim_run("test_jsons/classes_lanes.json", 20.0).unwrap().t,
+            PI / 0.5,
+            max_relative = RELATIVE
+        );
+    }
+
+    #[test]
+    /**
+        Cars cannot leave while pedestrians are on the crossing
+    */
+    fn sim_crossing() {
+        for filename in [
+            "test_jsons/crossing_spillback.json",
+            "test_jsons/crossing_no_spillback.json",
+        ] {
+            let sim = sim_run(filename, 10.0).unwrap();
+            let (_, blocked_end) = *sim.setting.crossings[0].gen_blocked().last().unwrap();
+            let stats = sim.stats();
+            assert_eq!(stats.n_finished, 2);
+            assert_eq!(stats.crossings[0].n_pedestrians, 36);
+            assert_abs_diff_eq!(stats.crossings[0].blocked_time, blocked_end, epsilon = 0.1);
+            // both would have left before t = 1.2 without pedestrians
+            let car = &stats.classes[sim.setting.class_index("car").unwrap()];
+            assert!(
+                car.mean_travel_time >= blocked_end,
+                "{filename}: left too early"
+            );
+        }
+    }
+
+    #[test]
+    /**
+        No car enters through a red signal and every car eventually enters
+    */
+    fn sim_signal() {
+        for filename in [
+            "test_jsons/signal_fixed_time.json",
+            "test_jsons/signal_queue_actuated.json",
+        ] {
+            let mut sim = RoundaboutSim::from_json(filename).unwrap();
+            let n_cars = sim.n_waiting();
+            let mut finished = false;
+            while !finished && sim.t < 100.0 {
+                let red = sim.signal_state(0) == Some(SignalState::Red);
+                let n_waiting = sim.n_waiting();
+                finished = sim.update();
+                if red {
+                    assert_eq!(sim.n_waiting(), n_waiting, "{filename}: entered at red");
+                }
+            }
+            assert!(finished);
+            assert_eq!(sim.finished_cars.len(), n_cars);
+            let stats = sim.stats();
+            assert!(stats.signals[0].green_time > 0.0 && stats.signals[0].red_time > 0.0);
+        }
+    }
+
+    #[test]
+    /**
+        The event engine agrees with the tick engine in fewer steps
+    */
+    fn sim_event_engine() {
+        for filename in [
+            "test_jsons/single.json",
+            "test_jsons/fast_slow_2.json",
+            "test_jsons/side_fast_slow.json",
+            "test_jsons/side_fast_slow_inward.json",
+            "test_jsons/classes_follow.json",
+            "test_jsons/crossing_spillback.json",
+            "test_jsons/signal_fixed_time.json",
+            "test_jsons/od_demand.json",
+            "test_jsons/circular_360.json",
+        ] {
+            let run = |engine: Engine| {
+                let mut sim = RoundaboutSim::from_json(filename).unwrap();
+                sim.setting.engine = engine;
+                while !sim.update() {
+                    assert!(sim.t < 100.0, "{filename}: not finished");
+                }
+                sim
+            };
+            let tick = run(Engine::Tick);
+            let event = run(Engine::Event);
+            assert_eq!(event.finished_cars.len(), tick.finished_cars.len());
+            assert_relative_eq!(event.t, tick.t, max_relative = RELATIVE);
+            // a run done in a single tick cannot take fewer steps
+            assert!(
+                event.step < tick.step || tick.step == 1,
+                "{filename}: {} steps",
+                event.step
+            );
+            // and so do the travel times
+            for (e, t) in event
+                .stats()
+                .classes
+                .iter()
+                .zip(tick.stats().classes.iter())
+            {
+                assert_relative_eq!(
+                    e.mean_travel_time,
+                    t.mean_travel_time,
+                    max_relative = RELATIVE
+                );
+                assert_relative_eq!(
+                    e.max_travel_time,
+                    t.max_travel_time,
+                    max_relative = RELATIVE
+                );
+            }
+        }
+        // arrivals are timed at the limited speed, not the desired one
+        for (limit, t_exit) in [
+            (None, PI / 0.4),
+            (Some((1.0, 2.0)), 1.0 + 1.0 / 0.4 + (PI - 2.0)),
+        ] {
+            let run = |engine: Engine| {
+                let mut sim = RoundaboutSimBuilder::new()
+                    .geometry(4, &[1.0])
+                    .engine(engine)
+                    .speed_limit(0, 0.4, limit)
+                    .car(0, 0, 0.0, 1.0, 2, None)
+                    .build()
+                    .unwrap();
+                while !sim.update() {}
+                sim
+            };
+            let tick = run(Engine::Tick);
+            let event = run(Engine::Event);
+            let event_exit = event.find_car(0).unwrap().t_exit.unwrap();
+            let tick_exit = tick.find_car(0).unwrap().t_exit.unwrap();
+            assert_relative_eq!(event_exit, t_exit, max_relative = 1e-3);
+            // the tick engine only sees the limit change at its ticks
+            assert!((tick_exit - event_exit).abs() <= 2.0 * tick.setting.tick);
+            assert!(event.step <= 4, "{limit:?}: {} steps", event.step);
+        }
+    }
+
+    #[test]
+    /**
+        Many cars keep the lanes consistent (checked by debug assertions) and none
+        of them is lost
+    */
+    fn sim_many_cars() {
+        let mut sim = RoundaboutSim::from_json("test_jsons/rand_3000_8_6..1.json").unwrap();
+        for _ in 0..100 {
+            sim.update();
+        }
+        let stats = sim.stats();
+        assert!(stats.n_finished > 0);
+        assert_eq!(stats.n_finished + stats.n_unfinished, 3000);
+    }
+
+    #[test]
+    #[ignore]
+    /**
+        10k cars in bumper to bumper platoons with the event engine, run with
+        `cargo test --release -- --ignored sim_10k_platoons --nocapture` for the timing
+    */
+    fn sim_10k_platoons() {
+        let mut builder = RoundaboutSimBuilder::new()
+            .geometry(8, &[3.2, 3.1, 3.0, 2.9])
+            .engine(Engine::Event);
+        for id in 0..10000 {
+            let (lane, k) = (id % 4, id / 4);
+            let theta = 2.0 * PI * k as Real / 2500.0;
+            let vel = 1.0 + 0.1 * (k % 3) as Real;
+            builder = builder.car(id, lane, theta, vel, k % 8, None);
+        }
+        let mut sim = builder.build().unwrap();
+        let start = std::time::Instant::now();
+        for _ in 0..200 {
+            sim.update();
+        }
+        println!("200 steps of 10k cars in {:?}", start.elapsed());
+        assert_eq!(sim.stats().n_finished + sim.stats().n_unfinished, 10000);
+    }
+
+    #[test]
+    /**
+        A simulation restored from a snapshot continues bit-for-bit the same,
+        for both engines
+    */
+    fn sim_snapshot() {
+        // the second one is saved while incidents are going on
+        for (filename, t_saved) in [
+            ("test_jsons/snapshot_mixed.json", 20.0),
+            ("test_jsons/incident_lane_blocked.json", 2.0),
+        ] {
+            for engine in [Engine::Tick, Engine::Event] {
+                let mut sim = RoundaboutSim::from_json(filename).unwrap();
+                sim.setting.engine = engine;
+                while sim.t < t_saved {
+                    sim.update();
+                }
+                let saved = json::stringify(sim.snapshot());
+                let mut restored = RoundaboutSim::restore(&json::parse(&saved).unwrap()).unwrap();
+                assert_eq!(json::stringify(restored.snapshot()), saved);
+                assert_eq!(restored.setting.incidents, sim.setting.incidents);
+                while sim.t < 80.0 && !sim.update() {}
+                while restored.t < 80.0 && !restored.update() {}
+                assert_eq!(restored.step, sim.step);
+                assert_eq!(
+                    json::stringify(restored.snapshot()),
+                    json::stringify(sim.snapshot())
+                );
+            }
+        }
+        let mut sim = RoundaboutSim::from_json("test_jsons/incident_lane_blocked.json").unwrap();
+        while sim.t < 2.0 {
+            sim.update();
+        }
+        assert_eq!(
+            sim.snapshot()["incidents_active"],
+            json::array![true, true, true]
+        );
+    }
+
+    #[test]
+    /**
+        No invariant is broken on the way, and broken ones are recorded with the
+        step and the cars involved without ending the run
+    */
+    fn sim_invariants() {
+        for filename in [
+            "test_jsons/first_switch_3.json",
+            "test_jsons/side_fast_slow_inward.json",
+            "test_jsons/classes_follow.json",
+            "test_jsons/solid_line_early_exit.json",
+            "test_jsons/crossing_spillback.json",
+            "test_jsons/snapshot_mixed.json",
+            "test_jsons/rand_30_4_5..1.json",
+        ] {
+            for engine in [Engine::Tick, Engine::Event] {
+                let mut sim = RoundaboutSim::from_json(filename).unwrap();
+                sim.setting.engine = engine;
+                sim.setting.check_invariants = true;
+                while sim.t < 100.0 && !sim.update() {}
+                assert!(
+                    sim.violations().is_empty(),
+                    "{filename}: {}",
+                    sim.violations()[0]
+                );
+            }
+        }
+        // the cars overlap on purpose, loading the file refuses them
+        let path = "test_jsons/invariant_overlap.json";
+        assert!(RoundaboutSim::from_json(path).is_none());
+        let jobj = config::read_json(path).unwrap();
+        let mut sim = RoundaboutSim::new(RoundaboutSimSetting::new(&jobj).unwrap(), &jobj).unwrap();
+        // recorded without ending the run
+        assert!(!sim.update());
+        let violation = &sim.violations()[0];
+        assert_eq!(violation.step, 1);
+        assert_eq!(violation.kind, ViolationKind::Overlap);
+        assert_eq!(violation.cars, [0, 1]);
+    }
+
+    #[test]
+    /**
+        Every problem of a configuration is reported with its path, the
+        test_jsons have none apart from the deliberate ones
+    */
+    fn sim_validate() {
+        let content = std::fs::read_to_string("test_jsons/invalid.json").unwrap();
+        let problems = validate(&json::parse(&content).unwrap());
+        let paths: Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.tick",
+                "$.engine",
+                "$.demand.od",
+                "$.demand.classes.tram",
+                "$.signals[0].green",
+                "$.signals[1].inter",
+                "$.signals[1].max_green",
+                "$.init.1.dst",
+                "$.init.2.speed",
+                "$.init.2.vel",
+                "$.init.0",
+            ],
+            "{problems:?}"
+        );
+        assert_eq!(
+            problems[7].message,
+            "must be less than 4, the number of intersections"
+        );
+        assert_eq!(problems[10].message, "overlaps car 1 on lane 0");
+        // the keys known to each kind of tagged object are its own
+        let mut jobj = RoundaboutSimSetting::gen_circular(2);
+        jobj["signals"] = json::array![json::object! {
+            type: "FixedTime", inter: 0, green: 1.0, red: 1.0, queue: 3,
+        }];
+        jobj["incidents"] = json::array![json::object! {
+            type: "ExitClosed", inter: 1, lane: 0,
+        }];
+        let problems: Vec<String> = validate(&jobj).iter().map(Problem::to_string).collect();
+        assert_eq!(
+            problems,
+            [
+                "$.signals[0].queue: unknown key",
+                "$.incidents[0].lane: unknown key",
+            ]
+        );
+        for entry in std::fs::read_dir("test_jsons").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            if ["invalid.json", "invariant_overlap.json"].contains(&name) {
+                continue;
+            }
+            let problems = validate(&config::read_json(path.to_str().unwrap()).unwrap());
+            assert!(problems.is_empty(), "{name}: {}", problems[0]);
+        }
+    }
+
+    #[test]
+    /**
+        Configs parse into typed structs strictly, write back unchanged, and old
+        versions are migrated
+    */
+    fn sim_config() {
+        for entry in std::fs::read_dir("test_jsons").unwrap() {
+            let path = entry.unwrap().path();
+            // networks are made of configs, they are not one
+            let is_network = path.to_str().unwrap().contains("network_");
+            if path.ends_with("invalid.json") || is_network {
+                continue;
+            }
+            let config = Config::from_file(path.to_str().unwrap()).unwrap();
+            assert_eq!(Config::from_json(&config.to_json()), Ok(config.clone()));
+            // the setting writes everything it read
+            let jobj = config.to_json();
+            let setting = RoundaboutSimSetting::new(&jobj).unwrap();
+            let mut written = setting.to_json();
+            written["init"] = jobj["init"].clone();
+            let again = RoundaboutSimSetting::new(&written).unwrap();
+            assert_eq!(again.to_json(), setting.to_json(), "{path:?}");
+            assert_eq!(Config::from_json(&written).unwrap().init, config.init);
+        }
+        // inconsistent values are rejected instead of panicking
+        let mut config = Config::from_file("test_jsons/first_switch_3.json").unwrap();
+        config.r_lanes = vec![0.7, 1.0];
+        assert!(config.build().is_none());
+        let problems = config.try_build().err().unwrap();
+        assert_eq!(problems[0].path, "$.r_lanes[1]");
+        assert!(RoundaboutSimSetting::from_config(&config).is_none());
+        let setting = RoundaboutSimSetting {
+            switch_policy: SwitchPolicy::SwitchFirst,
+            ..RoundaboutSimSetting::default()
+        };
+        assert_eq!(setting.to_json()["switch_policy"], "SwitchFirst");
+        let legacy = json::object! {
+            n_inter: 2,
+            tick: 0.1,
+            r_lanes: [1.0],
+            init: {"0": {id: 0, pos: "0+0i", action: "Straight", r: 0, lane: 0, dst: 1, theta: 0.2}},
+        };
+        let config = Config::from_json(&legacy).unwrap();
+        assert_eq!(config.version, config::CONFIG_VERSION);
+        assert_eq!(config.init[&0].theta, 0.2);
+        let mut unknown = legacy.clone();
+        unknown["version"] = config::CONFIG_VERSION.into();
+        let err = Config::from_json(&unknown).unwrap_err();
+        assert!(err.starts_with("unknown field"), "{err}");
+        unknown["version"] = (config::CONFIG_VERSION + 1).into();
+        assert!(Config::from_json(&unknown).is_err());
+        // a fixture kept with the keys of the first version
+        let legacy = config::read_json("test_jsons/fast_slow_2.json").unwrap();
+        assert!(legacy["init"]["0"].has_key("pos"));
+        assert!(validate(&legacy).is_empty());
+        assert_eq!(Config::from_json(&legacy).unwrap().init[&1].theta, 0.0);
+    }
+
+    #[test]
+    /**
+        TOML and YAML configs have the structure of the json ones, converting
+        between the formats keeps the config
+    */
+    fn sim_formats() {
+        let config = Config::from_file("test_jsons/classes_follow.json").unwrap();
+        for path in [
+            "test_jsons/classes_follow.toml",
+            "test_jsons/classes_follow.yaml",
+        ] {
+            assert_eq!(Config::from_file(path), Ok(config.clone()));
+            let sim = sim_run(path, 20.0).unwrap();
+            let expected = sim_run("test_jsons/classes_follow.json", 20.0).unwrap();
+            assert_eq!(sim.snapshot(), expected.snapshot());
+        }
+        let config = Config::from_file("test_jsons/snapshot_mixed.json").unwrap();
+        for format in [Format::Json, Format::Toml, Format::Yaml] {
+            let written = config.write(format);
+            let jobj = config::parse_json(&written, format).unwrap();
+            assert_eq!(Config::from_json(&jobj), Ok(config.clone()), "{written}");
+        }
+        assert_eq!(Format::of("a/b.yml"), Format::Yaml);
+        assert_eq!(Format::of("snapshot"), Format::Json);
+    }
+
+    #[test]
+    /**
+        Cars are sampled every interval, the csv and the columnar format keep
+        every row
+    */
+    fn sim_trajectory() {
+        let mut sim = RoundaboutSim::from_file("test_jsons/first_switch_3.json").unwrap();
+        let mut trajectory = Trajectory::new(0.5);
+        trajectory.record(&sim);
+        while sim.t < 10.0 && !sim.update() {
+            trajectory.record(&sim);
+        }
+        assert!(!trajectory.is_empty());
+        for i in 0..trajectory.len() {
+            let samples = trajectory.t[i] / 0.5;
+            assert_abs_diff_eq!(samples, samples.round(), epsilon = 0.01);
+            let r = trajectory.x[i].hypot(trajectory.y[i]);
+            assert_abs_diff_eq!(r, trajectory.r[i], epsilon = 1e-4);
+        }
+        assert_eq!(trajectory.t[0], 0.0);
+        let mut csv = vec![];
+        trajectory.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), trajectory.len() + 1);
+        assert!(csv.starts_with("t,id,x,y,r,theta,lane,action,vel\n"));
+        let mut columnar = vec![];
+        trajectory.write_columnar(&mut columnar).unwrap();
+        let read = Trajectory::read_columnar(&mut columnar.as_slice()).unwrap();
+        assert_eq!(read.t, trajectory.t);
+        assert_eq!(read.id, trajectory.id);
+        assert_eq!(read.theta, trajectory.theta);
+        assert_eq!(read.action, trajectory.action);
+        assert_eq!(read.vel, trajectory.vel);
+        assert!(columnar.len() < csv.len());
+    }
+
+    #[test]
+    /**
+        A simulation built from Rust runs like the same configuration file, and
+        problems are reported like by validate
+    */
+    fn sim_builder() {
+        let theta: Real = "3.1415926".parse().unwrap(); // as in the file
+        let mut sim = RoundaboutSimBuilder::new()
+            .geometry(4, &[1.0, 0.7])
+            .tick(0.1)
+            .switch_policy(SwitchPolicy::SwitchFirst)
+            .car(0, 1, theta, 1.0, 2, None)
+            .car(1, 0, 3.04, 1.0, 2, Some("ShortestDist"))
+            .car(2, 0, 3.2, 0.5, 3, None)
+            .build()
+            .unwrap();
+        while !sim.update() {}
+        let expected = sim_run("test_jsons/first_switch_3.json", 10.0).unwrap();
+        assert_eq!(sim.t, expected.t);
+        assert_eq!(sim.stats().to_string(), expected.stats().to_string());
+        let problems = RoundaboutSimBuilder::new()
+            .geometry(4, &[1.0, 0.7])
+            .tick(0.0)
+            .car(0, 2, 0.0, 1.0, 1, None)
+            .car(1, 0, 1.0, -1.0, 4, None)
+            .car(1, 0, 1.0, 1.0, 1, None)
+            .build()
+            .err()
+            .unwrap();
+        let paths: Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, ["$.init.1", "$.tick", "$.init.0.lane"]);
+    }
+
+    #[test]
+    /**
+        Cars can be looked at from outside, on the roundabout, by lane, by id and
+        once finished
+    */
+    fn sim_query() {
+        let mut sim = RoundaboutSim::from_file("test_jsons/first_switch_3.json").unwrap();
+        assert_eq!(sim.active_cars().count(), 3);
+        let car = sim.find_car(0).unwrap();
+        assert_eq!((car.lane, car.arm, car.t_exit), (1, 2, None));
+        assert_eq!(car.class, "car");
+        assert_abs_diff_eq!(car.r, 0.7);
+        assert_abs_diff_eq!(car.x.hypot(car.y), car.r, epsilon = 1e-6);
+        // ordered by angle in (-PI, PI]
+        let ids: Vec<usize> = sim.lane_cars(0).map(|car| car.id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(sim.lane_cars(2).count(), 0);
+        sim.update();
+        assert_eq!(sim.find_car(0).unwrap().action, Action::Switch(-1));
+        while !sim.update() {}
+        assert_eq!(sim.active_cars().count(), 0);
+        let exited: Vec<CarState> = sim.exited_cars().collect();
+        assert_eq!(
+            exited.iter().map(|car| car.id).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert!(exited.iter().all(|car| car.t_exit.is_some()));
+        assert_eq!(sim.find_car(2), exited.last().cloned());
+        assert_eq!(sim.find_car(3), None);
+    }
+
+    #[test]
+    /**
+        Runs stop at the first condition that holds, observers see every step
+    */
+    fn sim_run_until() {
+        let path = "test_jsons/first_switch_3.json";
+        let (sim, reason) = run_file(path, &mut [StopCondition::Time(1.0)]).unwrap();
+        assert_eq!(reason, StopReason::Time);
+        assert!(sim.t >= 1.0 && sim.active_cars().count() > 0);
+        let mut sim = RoundaboutSim::from_file(path).unwrap();
+        let mut steps = 0;
+        let conditions = &mut [StopCondition::Steps(10), StopCondition::Time(100.0)];
+        let reason = sim.run_until(conditions, |_| steps += 1);
+        assert_eq!((reason, steps), (StopReason::Steps, 10));
+        let (sim, reason) = run_file(path, &mut [StopCondition::Finished(2)]).unwrap();
+        assert_eq!(reason, StopReason::Finished);
+        assert_eq!(sim.exited_cars().count(), 2);
+        let predicate =
+            StopCondition::Predicate(Box::new(|sim| sim.find_car(0).unwrap().lane == 0));
+        let (sim, reason) = run_file(path, &mut [predicate]).unwrap();
+        assert_eq!(reason, StopReason::Predicate);
+        assert_eq!(sim.find_car(0).unwrap().lane, 0);
+        let (sim, reason) = run_file(path, &mut [StopCondition::AllFinished]).unwrap();
+        assert_eq!(reason, StopReason::AllFinished);
+        assert_eq!(sim.t, sim_run(path, -1.0).unwrap().t);
+        // cars wait on the roundabout while pedestrians cross
+        let path = "test_jsons/crossing_spillback.json";
+        let (sim, reason) = run_file(path, &mut [StopCondition::Deadlock(0.5)]).unwrap();
+        assert_eq!(reason, StopReason::Deadlock);
+        assert!(sim.active_cars().all(|car| car.vel == 0.0));
+        let (_, reason) = run_file(path, &mut [StopCondition::Deadlock(2.0)]).unwrap();
+        assert_eq!(reason, StopReason::AllFinished);
+        let jobj = config::read_json("test_jsons/invariant_overlap.json").unwrap();
+        let mut sim = RoundaboutSim::new(RoundaboutSimSetting::new(&jobj).unwrap(), &jobj).unwrap();
+        assert_eq!(sim.run_until(&mut [], |_| {}), StopReason::Violation);
+        assert!(!sim.violations().is_empty());
+        // a restored run keeps what was found so far
+        let restored = RoundaboutSim::restore(&sim.snapshot()).unwrap();
+        let found = |sim: &RoundaboutSim| -> Vec<String> {
+            sim.violations().iter().map(|v| v.to_string()).collect()
+        };
+        assert_eq!(found(&restored), found(&sim));
+        // queued for good in front of a lane that never opens again
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSimBuilder::new()
+                .geometry(4, &[1.0])
+                .engine(engine)
+                .car(0, 0, 0.0, 1.0, 3, None)
+                .car(1, 0, 0.5, 1.0, 3, None)
+                .car(2, 0, 1.0, 1.0, 3, None)
+                .incident(Incident {
+                    start: 0.0,
+                    end: Real::INFINITY,
+                    kind: IncidentKind::LaneBlocked {
+                        lane: 0,
+                        from: 2.0,
+                        to: 2.5,
+                    },
+                    active: false,
+                })
+                .build()
+                .unwrap();
+            let reason = sim.run_until(&mut [StopCondition::Time(100.0)], |_| {});
+            assert_eq!(reason, StopReason::Deadlock, "{engine:?}");
+            assert!(sim.is_stalled() && sim.t < 100.0);
+            assert!(
+                RoundaboutSim::restore(&sim.snapshot())
+                    .unwrap()
+                    .is_stalled()
+            );
+            assert_eq!(sim.active_cars().count(), 3);
+            assert!(
+                sim.active_cars()
+                    .all(|car| car.vel == 0.0 && car.theta < 2.0)
+            );
+        }
+    }
+
+    #[test]
+    /**
+        Cars can be removed, rerouted and slowed down mid-run, by scripted events
+        or by calls between steps
+    */
+    fn sim_events() {
+        let path = "test_jsons/events_scripted.json";
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSim::from_file(path).unwrap();
+            sim.setting.engine = engine;
+            while sim.t < 0.8 {
+                sim.update();
+            }
+            assert_eq!(sim.find_car(1).unwrap().arm, 3);
+            assert_eq!(sim.find_car(2).unwrap().desired_vel, 0.5);
+            let saved = json::stringify(sim.snapshot());
+            let mut restored = RoundaboutSim::restore(&json::parse(&saved).unwrap()).unwrap();
+            while !sim.update() {}
+            while !restored.update() {}
+            assert_eq!(
+                json::stringify(restored.snapshot()),
+                json::stringify(sim.snapshot())
+            );
+            assert!(sim.violations().is_empty());
+            let removed: Vec<CarState> = sim.removed_cars().collect();
+            assert_eq!(removed.len(), 1);
+            assert_eq!((removed[0].id, removed[0].t_exit.is_some()), (0, true));
+            assert!(sim.exited_cars().all(|car| car.id != 0));
+            let stats = sim.stats();
+            assert_eq!((stats.n_finished, stats.n_removed), (2, 1));
+            // car 1 leaves a quarter lap later than it would have at 2
+            assert_relative_eq!(
+                sim.find_car(1).unwrap().t_exit.unwrap(),
+                1.5 * PI - 1.0,
+                max_relative = RELATIVE
+            );
+            assert_relative_eq!(
+                sim.find_car(2).unwrap().t_exit.unwrap(),
+                (2.0 * PI - 4.0) / 0.5,
+                max_relative = RELATIVE
+            );
+        }
+        let mut sim = RoundaboutSim::from_file("test_jsons/first_switch_3.json").unwrap();
+        assert!(sim.reroute_car(2, 0));
+        assert!(!sim.reroute_car(2, 4));
+        assert!(!sim.set_car_vel(1, 0.0));
+        assert!(sim.set_car_vel(1, 2.0));
+        assert_eq!(sim.remove_car(0).unwrap().id, 0);
+        assert_eq!(sim.remove_car(0), None);
+        assert_eq!(sim.lane_cars(1).count(), 0);
+        while !sim.update() {}
+        assert_eq!(sim.find_car(2).unwrap().arm, 0);
+        assert_eq!(sim.exited_cars().count(), 2);
+        let mut jobj = json::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        jobj["events"][0]["type"] = "Stop".into();
+        jobj["events"][2]["dst"] = 4.into();
+        jobj["events"][3]["vel"] = 0.0.into();
+        jobj["events"][3]["lane"] = 0.into();
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.events[0].type",
+                "$.events[2].dst",
+                "$.events[3].lane",
+                "$.events[3].vel"
+            ]
+        );
+    }
+
+    #[test]
+    /**
+        Cars go around blocked arcs or wait for them to clear, miss closed exits and
+        slow down in slow zones
+    */
+    fn sim_incidents() {
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim =
+                RoundaboutSim::from_file("test_jsons/incident_lane_blocked.json").unwrap();
+            sim.setting.engine = engine;
+            let reason = sim.run_until(&mut [StopCondition::Time(100.0)], |sim| {
+                for car in sim.active_cars() {
+                    assert!(car.lane != 1 || car.theta < 1.0 || car.theta > 2.0);
+                    // capped from the step after entering
+                    let in_zone = car.theta > 4.0 + 0.1 && car.theta < 5.0;
+                    if car.lane == 0 && in_zone && car.action == Action::Straight {
+                        assert!(car.vel <= 0.5 + 1e-4, "{car:?}");
+                    }
+                }
+            });
+            assert_eq!(reason, StopReason::AllFinished);
+            // car 1 reaches its exit at PI - 0.5 while it is closed
+            let t_exit = sim.find_car(1).unwrap().t_exit.unwrap();
+            assert!(t_exit > 3.0 * PI - 0.5, "{t_exit}");
+        }
+        // a single lane blocked until 5, the car waits in front of it
+        let mut sim = RoundaboutSimBuilder::new()
+            .geometry(4, &[1.0])
+            .check_invariants(true)
+            .car(0, 0, 0.0, 1.0, 2, None)
+            .incident(Incident {
+                start: 0.0,
+                end: 5.0,
+                kind: IncidentKind::LaneBlocked {
+                    lane: 0,
+                    from: 0.5,
+                    to: 1.0,
+                },
+                active: false,
+            })
+            .build()
+            .unwrap();
+        let mut waited = false;
+        let reason = sim.run_until(&mut [], |sim| {
+            let car = sim.find_car(0).unwrap();
+            if sim.t < 5.0 && car.t_exit.is_none() {
+                assert!(car.theta <= 0.5);
+                waited |= car.vel == 0.0;
+            }
+        });
+        assert_eq!(reason, StopReason::AllFinished);
+        assert!(waited);
+        let t_exit = sim.find_car(0).unwrap().t_exit.unwrap();
+        assert_relative_eq!(t_exit, 5.0 + PI - 0.5, max_relative = RELATIVE);
+        // the same in a slow zone, the car drives up to the blocked arc at its own speed
+        for engine in [Engine::Tick, Engine::Event] {
+            let slow_zone = Incident {
+                start: 0.0,
+                end: Real::INFINITY,
+                kind: IncidentKind::SlowZone {
+                    lane: 0,
+                    from: 0.0,
+                    to: 1.0,
+                    vel: 0.2,
+                },
+                active: false,
+            };
+            let mut sim = RoundaboutSimBuilder::new()
+                .geometry(4, &[1.0])
+                .engine(engine)
+                .check_invariants(true)
+                .car(0, 0, 0.0, 1.0, 2, None)
+                .incident(slow_zone)
+                .incident(Incident {
+                    start: 0.0,
+                    end: 5.0,
+                    kind: IncidentKind::LaneBlocked {
+                        lane: 0,
+                        from: 0.5,
+                        to: 1.0,
+                    },
+                    active: false,
+                })
+                .build()
+                .unwrap();
+            let mut gap = Real::INFINITY;
+            let reason = sim.run_until(&mut [StopCondition::Time(100.0)], |sim| {
+                let car = sim.find_car(0).unwrap();
+                if sim.t < 5.0 && car.vel == 0.0 {
+                    gap = gap.min(0.5 - car.theta);
+                }
+            });
+            assert_eq!(reason, StopReason::AllFinished);
+            assert!((0.0..2e-3).contains(&gap), "{engine:?}: {gap}");
+        }
+    }
+
+    #[test]
+    /**
+        Cars keep to the speed limits of lanes and segments and to the lateral
+        acceleration limit of each lane
+    */
+    fn sim_speed_limits() {
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSimBuilder::new()
+                .geometry(4, &[1.0])
+                .engine(engine)
+                .lateral_accel(0.64)
+                .speed_limit(0, 0.4, Some((1.0, 2.0)))
+                .car(0, 0, 0.0, 1.0, 2, None)
+                .build()
+                .unwrap();
+            assert_eq!(sim.setting.speed_limit(0, 0.5), 0.8);
+            assert_eq!(sim.setting.speed_limit(0, 1.5), 0.4);
+            while !sim.update() {}
+            // 0.8 on the lane and 0.4 on the arc from 1 to 2
+            assert_relative_eq!(
+                sim.find_car(0).unwrap().t_exit.unwrap(),
+                1.0 / 0.8 + 1.0 / 0.4 + (PI - 2.0) / 0.8,
+                max_relative = 1e-2
+            );
+        }
+        // a driver leaves a slow lane when the detour is faster
+        for (limit, switched) in [(None, false), (Some(0.4), true)] {
+            let mut builder = RoundaboutSimBuilder::new().geometry(4, &[1.0, 0.5]).car(
+                0,
+                0,
+                0.0,
+                1.0,
+                1,
+                Some("ShortestDist"),
+            );
+            if let Some(vel) = limit {
+                builder = builder.speed_limit(0, vel, None);
+            }
+            let mut sim = builder.build().unwrap();
+            let mut inner = false;
+            sim.run_until(&mut [StopCondition::Time(100.0)], |sim| {
+                inner |= sim.active_cars().any(|car| car.lane == 1);
+            });
+            assert_eq!(inner, switched, "{limit:?}");
+        }
+        let path = "test_jsons/rand_30_4_5..1.json";
+        let mut jobj = json::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        jobj["lateral_accel"] = 0.5.into();
+        let mut sim = Config::from_json(&jobj).unwrap().build().unwrap();
+        let reason = sim.run_until(&mut [StopCondition::Time(3000.0)], |sim| {
+            for car in sim.active_cars() {
+                if car.action == Action::Straight {
+                    assert!(car.vel <= (0.5 * car.r).sqrt() + 1e-4, "{car:?}");
+                }
+            }
+        });
+        assert_eq!(reason, StopReason::AllFinished);
+    }
+
+    #[test]
+    /**
+        A clockwise roundabout runs like the counter-clockwise one with the same
+        configuration, mirrored across the x axis
+    */
+    fn sim_circulation() {
+        let path = "test_jsons/first_switch_3.json";
+        let mut config = Config::from_file(path).unwrap();
+        config.circulation = Some(Circulation::Clockwise);
+        let mut clockwise = config.build().unwrap();
+        assert_eq!(clockwise.setting.circulation, Circulation::Clockwise);
+        let mut counter = RoundaboutSim::from_file(path).unwrap();
+        loop {
+            let finished = counter.update();
+            assert_eq!(clockwise.update(), finished);
+            let mirrored: Vec<CarState> = counter
+                .active_cars()
+                .map(|car| CarState { y: -car.y, ..car })
+                .collect();
+            assert_eq!(clockwise.active_cars().collect::<Vec<_>>(), mirrored);
+            if finished {
+                break;
+            }
+        }
+        // intersection 1 of 4 is a quarter lap clockwise, below the center
+        let setting = &clockwise.setting;
+        let pos = setting.world_pos(0, 0.0, setting.inter_theta(1));
+        assert_abs_diff_eq!(pos.re, 0.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(pos.im, -1.0, epsilon = 1e-6);
+        assert_eq!(clockwise.setting.to_json()["circulation"], "Clockwise");
+        let mut jobj = clockwise.setting.to_json();
+        jobj["circulation"] = "Left".into();
+        jobj["init"] = json::object! {};
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(paths, ["$.circulation"]);
+    }
+
+    #[test]
+    /**
+        Cars drive along elliptic and polygonal lanes at the distances of their
+        perimeters
+    */
+    fn sim_shapes() {
+        let ellipse = Shape::Ellipse { a: 2.0, b: 1.0 };
+        let curve = Curve::new(ellipse.clone());
+        assert_relative_eq!(curve.perimeter(), 9.6884, max_relative = 1e-4);
+        let square = Curve::new(Shape::Polyline {
+            points: vec![[1.0, 1.0], [-1.0, 1.0], [-1.0, -1.0], [1.0, -1.0]],
+            smooth: false,
+        });
+        assert_relative_eq!(square.perimeter(), 8.0, max_relative = 1e-6);
+        let pos = square.point(PI);
+        assert_abs_diff_eq!(pos.re, -1.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(pos.im, -1.0, epsilon = 1e-5);
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSimBuilder::new()
+                .shapes(4, std::slice::from_ref(&ellipse))
+                .engine(engine)
+                .car(0, 0, 0.0, 1.0, 2, None)
+                .build()
+                .unwrap();
+            let reason = sim.run_until(&mut [StopCondition::Time(100.0)], |sim| {
+                for car in sim.active_cars() {
+                    let on_ellipse = car.x * car.x / 4.0 + car.y * car.y;
+                    assert_abs_diff_eq!(on_ellipse, 1.0, epsilon = 1e-3);
+                }
+            });
+            assert_eq!(reason, StopReason::AllFinished);
+            // half a lap at velocity 1
+            assert_relative_eq!(
+                sim.find_car(0).unwrap().t_exit.unwrap(),
+                curve.perimeter() / 2.0,
+                max_relative = RELATIVE
+            );
+        }
+        // the lateral acceleration limit follows the curvature, b^2/a at the ends of
+        // the major axis and a^2/b at those of the minor one
+        assert_relative_eq!(curve.radius(0.0), 0.5, max_relative = 5e-2);
+        assert_relative_eq!(curve.radius(0.5 * PI), 4.0, max_relative = 5e-2);
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSimBuilder::new()
+                .shapes(4, std::slice::from_ref(&ellipse))
+                .engine(engine)
+                .lateral_accel(0.5)
+                .car(0, 0, 0.0, 1.0, 2, None)
+                .build()
+                .unwrap();
+            assert_relative_eq!(sim.setting.speed_limit(0, 0.0), 0.5, max_relative = 5e-2);
+            assert_relative_eq!(
+                sim.setting.speed_limit(0, 0.5 * PI),
+                Real::sqrt(2.0),
+                max_relative = 5e-2
+            );
+            let reason = sim.run_until(&mut [StopCondition::Time(100.0)], |sim| {
+                // around the ends of the major axis, the limit being sampled along the lane
+                for car in sim.active_cars().filter(|car| car.y.abs() < 0.1) {
+                    assert!(car.vel < 0.55, "{car:?}");
+                }
+            });
+            assert_eq!(reason, StopReason::AllFinished);
+            assert!(sim.find_car(0).unwrap().t_exit.unwrap() > curve.perimeter() / 2.0);
+        }
+        let sim = sim_run("test_jsons/ellipse_lanes.json", 100.0).unwrap();
+        assert_eq!(sim.finished_cars.len(), 2);
+        assert!(sim.violations().is_empty());
+        let jobj = sim.setting.to_json();
+        assert!(!jobj.has_key("r_lanes"));
+        assert_eq!(jobj["shapes"].len(), 2);
+        assert_eq!(jobj["shapes"][1]["type"], "Ellipse");
+        let mut jobj = Config::from_file("test_jsons/ellipse_lanes.json")
+            .unwrap()
+            .to_json();
+        jobj["shapes"][1]["a"] = 3.0.into();
+        jobj["shapes"][0]["smooth"] = true.into();
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(paths, ["$.shapes[0].smooth", "$.shapes[1]"]);
+        jobj["r_lanes"] = json::array![1.0, 0.5];
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(paths, ["$.shapes"]);
+    }
+
+    #[test]
+    /**
+        Cars of trips go from roundabout to roundabout over the links, keeping their
+        ids, and a full link holds them at the exit
+    */
+    fn sim_network() {
+        let path = "test_jsons/network_corridor.json";
+        let network = network_run(path, 200.0).unwrap();
+        assert!(network.t_done.iter().all(Option::is_some));
+        // a quarter lap, the link and three quarters of a lap
+        assert_relative_eq!(
+            network.trip_times()[1],
+            2.0 * PI + 5.0,
+            max_relative = RELATIVE
+        );
+        let ring = &network.rings[1];
+        assert_eq!(network.trip_of(0), None);
+        assert!(ring.find_car(0).unwrap().t_exit.is_some());
+        // one car at a time on the link from ring 0 to ring 1
+        let mut t_enter: Vec<Real> = [0, 2, 3, 4, 5]
+            .iter()
+            .map(|trip| {
+                let id = (0..20).find(|id| network.trip_of(*id) == Some(*trip));
+                ring.find_car(id.unwrap()).unwrap().t_enter
+            })
+            .collect();
+        t_enter.sort_by(Real::total_cmp);
+        // each starts on the link once the one before has left it
+        for pair in t_enter.windows(2) {
+            assert!(pair[1] - pair[0] >= 5.0 - 1e-3, "{t_enter:?}");
+        }
+        // ring 1 is idle until the car comes off the link
+        let mut network = RoundaboutNetwork::new(&json::object! {
+            rings: [
+                {n_inter: 4, r_lanes: [1.0], tick: 0.1, init: {}},
+                {n_inter: 4, r_lanes: [1.0], tick: 0.1, init: {}},
+            ],
+            links: [{from: [0, 0], to: [1, 2], travel_time: 5.0}],
+            trips: [{t: 10.0, ring: 0, from: 2, exits: [0, 1]}],
+        })
+        .unwrap();
+        let mut t = network.t;
+        while !network.update() {
+            assert!(network.t >= t);
+            t = network.t;
+        }
+        let t_exit = network.rings[0].find_car(0).unwrap().t_exit.unwrap();
+        let t_enter = network.rings[1].find_car(0).unwrap().t_enter;
+        assert!(
+            t_enter >= t_exit + 5.0 && t_enter < t_exit + 5.0 + 0.1,
+            "{t_enter}"
+        );
+        // half a lap, the link and three quarters of a lap
+        let expected = PI + 5.0 + 1.5 * PI;
+        assert_relative_eq!(network.trip_times()[0], expected, max_relative = RELATIVE);
+        let mut jobj = config::read_json(path).unwrap();
+        assert!(validate(&jobj).is_empty());
+        jobj["rings"][1]["tick"] = 0.0.into();
+        jobj["links"][0]["capacity"] = 0.into();
+        jobj["trips"][0]["exits"] = json::array![1, 0];
+        let paths: Vec<String> = validate(&jobj).into_iter().map(|p| p.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.rings[1].tick",
+                "$.links[0].capacity",
+                "$.trips[0].exits[0]"
+            ]
+        );
+    }
+
+    #[test]
+    /**
+        A car kept on the roundabout for a thousand laps is where its velocity puts it,
+        and leaves at the first pass of its exit once it opens
+    */
+    fn sim_long_run() {
+        let lap = 2.0 * PI;
+        let laps = 1000.0;
+        for engine in [Engine::Tick, Engine::Event] {
+            let mut sim = RoundaboutSimBuilder::new()
+                .geometry(2, &[1.0])
+                .tick(0.1)
+                .engine(engine)
+                .car(0, 0, 0.0, 1.0, 1, None)
+                .incident(Incident {
+                    start: 0.0,
+                    end: laps * lap,
+                    kind: IncidentKind::ExitClosed { inter: 1 },
+                    active: false,
+                })
+                .build()
+                .unwrap();
+            let mut worst: Real = 0.0;
+            while !sim.update() {
+                // at unit velocity from angle 0 the car has driven as long as the time
+                if let Some(car) = sim.find_car(0).filter(|car| car.t_exit.is_none()) {
+                    let drift = (car.theta - sim.t.rem_euclid(lap)).abs();
+                    worst = worst.max(drift.min(lap - drift));
+                }
+            }
+            assert!(worst < 1e-4, "{engine:?}: {worst}");
+            // within the step crossing the exit, the event engine lands on it
+            let late = sim.find_car(0).unwrap().t_exit.unwrap() - (laps + 0.5) * lap;
+            assert!((0.0..0.1 + 1e-3).contains(&late), "{engine:?}: {late}");
+            #[cfg(feature = "f64")]
+            if engine == Engine::Event {
+                assert!(late < 2e-4, "{late}");
+            }
+        }
+    }
+
     #[test]
     #[ignore]
     /**